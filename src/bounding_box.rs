@@ -23,7 +23,12 @@ impl PartialOrd for AABB {
 impl AABB {
     pub fn new(start: Vec3, end: Vec3) -> Self { Self { start, end } }
 
-    pub fn hit(&self, r: &crate::ray::Ray, mut t_min : f64, mut t_max : f64) -> bool {
+    pub fn hit(&self, r: &crate::ray::Ray, t_min : f64, t_max : f64) -> bool {
+        self.hit_span(r, t_min, t_max).is_some()
+    }
+
+    // the part of [t_min, t_max] the ray spends inside the box
+    pub fn hit_span(&self, r: &crate::ray::Ray, mut t_min : f64, mut t_max : f64) -> Option<(f64, f64)> {
         for a in 0..3 {
             let inv_d = 1. / r.dir[a];
             let mut t0 = (self.start[a] - r.origin[a]) * inv_d;
@@ -34,10 +39,34 @@ impl AABB {
             t_min = if t0 > t_min { t0 } else { t_min };
             t_max = if t1 < t_max { t1 } else { t_max };
             if t_max <= t_min {
-                return false;
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+
+    // smallest box around the points, padded so flat shapes still get hit
+    pub fn from_points(points : &[Vec3]) -> Self {
+        let pad = 0.0001;
+        let mut start = Vec3::of_scalar(f64::INFINITY);
+        let mut end = Vec3::of_scalar(f64::NEG_INFINITY);
+        for p in points {
+            for a in 0..3 {
+                start[a] = start[a].min(p[a] - pad);
+                end[a] = end[a].max(p[a] + pad);
             }
         }
-        true
+
+        Self::new(start, end)
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.start + self.end)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.end - self.start;
+        2. * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
     }
 
     pub fn combine(&self, other : &AABB) -> Self {
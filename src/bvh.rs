@@ -0,0 +1,532 @@
+use std::fmt;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use rayon::join;
+
+use crate::bounding_box::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+// buckets the centroids are sorted into along each axis
+const SAH_BINS: usize = 12;
+// cost of stepping through a node, relative to intersecting one object
+const TRAVERSAL_COST: f64 = 0.125;
+// leaves bigger than this are split even when the heuristic says not to
+pub const MAX_LEAF_SIZE: usize = 4;
+// subtrees smaller than this are built on the current thread
+const PARALLEL_THRESHOLD: usize = 4096;
+// refitted trees are rebuilt once their cost grows past this factor of the
+// cost they were built with
+pub const REBUILD_RATIO: f64 = 1.5;
+
+fn bin_of(c: f64, lo: f64, extent: f64) -> usize {
+    ((SAH_BINS as f64 * (c - lo) / extent) as usize).min(SAH_BINS - 1)
+}
+
+// binned surface area heuristic, picks the axis and plane that minimise the
+// expected cost of tracing a ray through the two halves. returns which side
+// of the items each one goes to and the axis they were split along, or None
+// when a leaf is cheaper. bbs and centroids are indexed by item
+// https://www.pbr-book.org/3ed-2018/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies
+pub fn sah_split(bbs: &[AABB], centroids: &[Point3], items: &[usize]) -> Option<(usize, Vec<bool>)> {
+    let n = items.len();
+    if n <= 1 {
+        return None;
+    }
+
+    let bounds = items_bb(bbs, items);
+    let first = centroids[items[0]];
+    let centroid_bounds = items
+        .iter()
+        .fold(AABB::new(first, first), |acc, &i| acc.combine(&AABB::new(centroids[i], centroids[i])));
+
+    // (axis, last bin on the left)
+    let mut best: Option<(usize, usize)> = None;
+    let mut best_cost = f64::INFINITY;
+    for axis in 0..3 {
+        let lo = centroid_bounds.start[axis];
+        let extent = centroid_bounds.end[axis] - lo;
+        // all centroids in the same plane, nothing to split on this axis
+        if extent <= 0. {
+            continue;
+        }
+
+        let mut counts = [0; SAH_BINS];
+        let mut bin_bbs: [Option<AABB>; SAH_BINS] = [None; SAH_BINS];
+        for &i in items {
+            let c = centroids[i];
+            let b = bin_of(c[axis], lo, extent);
+            counts[b] += 1;
+            bin_bbs[b] = Some(bin_bbs[b].map_or(bbs[i], |acc| acc.combine(&bbs[i])));
+        }
+
+        // sweep from the right so each split can read off the right half
+        let mut right_area = [0.; SAH_BINS];
+        let mut right_count = [0; SAH_BINS];
+        let mut acc: Option<AABB> = None;
+        let mut count = 0;
+        for b in (1..SAH_BINS).rev() {
+            if let Some(bb) = bin_bbs[b] {
+                acc = Some(acc.map_or(bb, |acc| acc.combine(&bb)));
+            }
+            count += counts[b];
+            right_area[b] = acc.map_or(0., |bb| bb.surface_area());
+            right_count[b] = count;
+        }
+
+        let mut acc: Option<AABB> = None;
+        let mut count = 0;
+        for b in 0..SAH_BINS - 1 {
+            if let Some(bb) = bin_bbs[b] {
+                acc = Some(acc.map_or(bb, |acc| acc.combine(&bb)));
+            }
+            count += counts[b];
+            if count == 0 || right_count[b + 1] == 0 {
+                continue;
+            }
+
+            let left_area = acc.map_or(0., |bb| bb.surface_area());
+            let cost = left_area * count as f64 + right_area[b + 1] * right_count[b + 1] as f64;
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, b));
+            }
+        }
+    }
+
+    let (axis, last) = match best {
+        Some(best) => best,
+        // every centroid is in the same spot
+        None if n > MAX_LEAF_SIZE => {
+            return Some((0, (0..n).map(|i| i < n / 2).collect()));
+        }
+        None => return None,
+    };
+
+    let area = bounds.surface_area();
+    let cost = if area > 0. { TRAVERSAL_COST + best_cost / area } else { TRAVERSAL_COST };
+    if n <= MAX_LEAF_SIZE && cost >= n as f64 {
+        return None;
+    }
+
+    let lo = centroid_bounds.start[axis];
+    let extent = centroid_bounds.end[axis] - lo;
+    Some((axis, items.iter().map(|&i| bin_of(centroids[i][axis], lo, extent) <= last).collect()))
+}
+
+fn items_bb(bbs: &[AABB], items: &[usize]) -> AABB {
+    items.iter().skip(1).fold(bbs[items[0]], |acc, &i| acc.combine(&bbs[i]))
+}
+
+#[derive(Clone, Copy)]
+pub struct BvhNode {
+    pub bb: AABB,
+    // leaves cover items start..start + count, inner nodes have count == 0
+    // and their children at (self + 1, right)
+    pub start: usize,
+    pub count: usize,
+    pub right: usize,
+    // axis the children were split along, the first child is on the low side
+    pub axis: usize,
+}
+
+#[derive(Clone)]
+pub struct BuildStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    // expected cost of a ray, see sah_cost
+    pub cost: f64,
+    pub build_time: Duration,
+}
+
+impl fmt::Display for BuildStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bvh: {} nodes, {} leaves, depth {}, cost {:.2}, built in {:.2?}",
+            self.nodes, self.leaves, self.depth, self.cost, self.build_time
+        )
+    }
+}
+
+// flat array of nodes in depth first order, so walking the tree needs no
+// pointer chasing or virtual calls. the items themselves live with the owner
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    pub stats: BuildStats,
+}
+
+// nodes of the subtree over items, which get reordered into leaf order. child
+// links are relative to the subtree, so the caller shifts them into place
+fn build_subtree(bbs: &[AABB], centroids: &[Point3], items: &mut [usize], start: usize) -> Vec<BvhNode> {
+    let leaf = BvhNode {
+        bb: items_bb(bbs, items),
+        start,
+        count: items.len(),
+        right: 0,
+        axis: 0,
+    };
+
+    let (axis, left) = match sah_split(bbs, centroids, items) {
+        Some(split) => split,
+        None => return vec![leaf],
+    };
+
+    let (lhs, rhs): (Vec<_>, Vec<_>) = items.iter().zip(left).partition(|(_, left)| *left);
+    let sorted: Vec<usize> = lhs.iter().chain(rhs.iter()).map(|(item, _)| **item).collect();
+    let mid = lhs.len();
+    items.copy_from_slice(&sorted);
+
+    let parallel = items.len() >= PARALLEL_THRESHOLD;
+    let (lhs, rhs) = items.split_at_mut(mid);
+    let (lhs, rhs) = if parallel {
+        join(
+            || build_subtree(bbs, centroids, lhs, start),
+            || build_subtree(bbs, centroids, rhs, start + mid),
+        )
+    } else {
+        (
+            build_subtree(bbs, centroids, lhs, start),
+            build_subtree(bbs, centroids, rhs, start + mid),
+        )
+    };
+
+    let right = 1 + lhs.len();
+    let mut nodes = Vec::with_capacity(right + rhs.len());
+    nodes.push(BvhNode {
+        count: 0,
+        right,
+        axis,
+        ..leaf
+    });
+    for (offset, subtree) in [(1, lhs), (right, rhs)] {
+        nodes.extend(subtree.into_iter().map(|mut node| {
+            if node.count == 0 {
+                node.right += offset;
+            }
+            node
+        }));
+    }
+    nodes
+}
+
+// longest path from the root to a leaf, counting the root as depth 1
+fn depth(nodes: &[BvhNode]) -> usize {
+    if nodes.is_empty() {
+        return 0;
+    }
+
+    let mut deepest = 0;
+    let mut stack = vec![(0, 1)];
+    while let Some((i, d)) = stack.pop() {
+        deepest = deepest.max(d);
+        if nodes[i].count == 0 {
+            stack.push((i + 1, d + 1));
+            stack.push((nodes[i].right, d + 1));
+        }
+    }
+    deepest
+}
+
+// expected cost of tracing a ray that hits the root through the tree, in units
+// of object intersections. a node is reached with probability proportional to
+// its surface area
+fn sah_cost(nodes: &[BvhNode]) -> f64 {
+    let root = match nodes.first() {
+        Some(node) => node.bb.surface_area(),
+        None => return 0.,
+    };
+    if root <= 0. {
+        return 0.;
+    }
+
+    nodes
+        .iter()
+        .map(|node| {
+            let per_hit = if node.count > 0 { node.count as f64 } else { TRAVERSAL_COST };
+            per_hit * node.bb.surface_area() / root
+        })
+        .sum()
+}
+
+impl Bvh {
+    // returns the tree and the order the items have to be rearranged into so
+    // each leaf covers a contiguous range of them. big subtrees are built in
+    // parallel
+    pub fn build(bbs: &[AABB]) -> (Self, Vec<usize>) {
+        let timer = Instant::now();
+        let centroids: Vec<Point3> = bbs.iter().map(|bb| bb.centroid()).collect();
+        let mut order: Vec<usize> = (0..bbs.len()).collect();
+        let nodes = if bbs.is_empty() {
+            vec![]
+        } else {
+            build_subtree(bbs, &centroids, &mut order, 0)
+        };
+
+        let stats = BuildStats {
+            nodes: nodes.len(),
+            leaves: nodes.iter().filter(|node| node.count > 0).count(),
+            depth: depth(&nodes),
+            cost: sah_cost(&nodes),
+            build_time: timer.elapsed(),
+        };
+        (Self { nodes, stats }, order)
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        self.nodes.first().map(|node| node.bb)
+    }
+
+    // updates the node bounds after the items moved, keeping the topology.
+    // bbs are in the rearranged order, one per item the tree was built over
+    pub fn refit(&mut self, bbs: &[AABB]) {
+        // children always come after their parent
+        for i in (0..self.nodes.len()).rev() {
+            let node = self.nodes[i];
+            self.nodes[i].bb = if node.count > 0 {
+                let leaf = &bbs[node.start..node.start + node.count];
+                leaf.iter().skip(1).fold(leaf[0], |acc, bb| acc.combine(bb))
+            } else {
+                self.nodes[i + 1].bb.combine(&self.nodes[node.right].bb)
+            };
+        }
+    }
+
+    pub fn cost(&self) -> f64 {
+        sah_cost(&self.nodes)
+    }
+
+    // whether refitting has made the tree slow enough that a rebuild pays off
+    pub fn degraded(&self) -> bool {
+        self.cost() > REBUILD_RATIO * self.stats.cost
+    }
+
+    // walks the leaves the ray passes through, nearer children first. leaf is
+    // handed each leaf's item range and the closest distance so far, and
+    // returns the new closest distance if it found something nearer, so the
+    // subtrees behind it get skipped
+    pub fn traverse(&self, ray: &Ray, t_min: f64, mut t_max: f64, mut leaf: impl FnMut(Range<usize>, f64) -> Option<f64>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if !node.bb.hit(ray, t_min, t_max) {
+                continue;
+            }
+
+            if node.count > 0 {
+                if let Some(t) = leaf(node.start..node.start + node.count, t_max) {
+                    t_max = t;
+                }
+                continue;
+            }
+
+            // the child popped first is the one nearer along the split axis
+            if ray.dir[node.axis] < 0. {
+                stack.push(i + 1);
+                stack.push(node.right);
+            } else {
+                stack.push(node.right);
+                stack.push(i + 1);
+            }
+        }
+    }
+
+    // whether leaf returns true for any leaf the ray passes through, stopping
+    // at the first one. order doesn't matter so children aren't sorted
+    pub fn any(&self, ray: &Ray, t_min: f64, t_max: f64, mut leaf: impl FnMut(Range<usize>) -> bool) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if !node.bb.hit(ray, t_min, t_max) {
+                continue;
+            }
+
+            if node.count > 0 {
+                if leaf(node.start..node.start + node.count) {
+                    return true;
+                }
+                continue;
+            }
+
+            stack.push(node.right);
+            stack.push(i + 1);
+        }
+        false
+    }
+}
+
+#[test]
+fn test_sah_split() {
+    let unit = |x: f64, y: f64| AABB::new(vec3![x, y, 0.], vec3![x + 1., y + 1., 1.]);
+
+    // two clusters far apart along y are split between them, even though the
+    // spread along x is larger
+    let mut bbs = vec![];
+    for i in 0..10 {
+        bbs.push(unit(i as f64 * 3., 0.));
+        bbs.push(unit(i as f64 * 3. + 1., 40.));
+    }
+    let centroids: Vec<_> = bbs.iter().map(|bb| bb.centroid()).collect();
+    let items: Vec<_> = (0..bbs.len()).collect();
+    let (axis, left) = sah_split(&bbs, &centroids, &items).unwrap();
+    assert_eq!(axis, 1);
+    for (bb, left) in bbs.iter().zip(left) {
+        assert_eq!(left, bb.start.y() < 20.);
+    }
+
+    // a couple of overlapping boxes aren't worth splitting
+    let bbs = vec![unit(0., 0.), unit(0.1, 0.)];
+    let centroids: Vec<_> = bbs.iter().map(|bb| bb.centroid()).collect();
+    assert!(sah_split(&bbs, &centroids, &[0, 1]).is_none());
+
+    // too many objects in one spot still get divided
+    let bbs = vec![unit(0., 0.); 9];
+    let centroids: Vec<_> = bbs.iter().map(|bb| bb.centroid()).collect();
+    let (_, left) = sah_split(&bbs, &centroids, &items[..9]).unwrap();
+    assert_eq!(left.iter().filter(|&&l| l).count(), 4);
+}
+
+#[test]
+fn test_hierarchy_matches_brute_force() {
+    use std::sync::Arc;
+
+    use rand::Rng;
+
+    use crate::material::*;
+    use crate::object::*;
+
+    let mut rng = rand::thread_rng();
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: crate::Color::white(),
+    });
+    let spheres: Vec<(Point3, f64)> = (0..200)
+        .map(|_| (Vec3::rand(-10., 10.), rng.gen_range(0.1..1.)))
+        .collect();
+    let objects: Vec<Box<dyn Object>> = spheres
+        .iter()
+        .map(|(c, r)| Box::new(Sphere::new(*c, *r, crate::Color::white(), mat.clone())) as Box<dyn Object>)
+        .collect();
+    let group = ObjectGroup::create_hierarchy(objects);
+
+    for _ in 0..500 {
+        let ray = Ray::new(Vec3::rand(-15., 15.), Vec3::rand(-1., 1.));
+        let expected = spheres
+            .iter()
+            .filter_map(|(c, r)| Sphere::new(*c, *r, crate::Color::white(), mat.clone()).hit(&ray, T_MIN, T_MAX))
+            .map(|hit| hit.t)
+            .fold(None, |acc: Option<f64>, t| Some(acc.map_or(t, |acc| acc.min(t))));
+        let got = group.hit(&ray, T_MIN, T_MAX).map(|hit| hit.t);
+        assert_eq!(got, expected);
+
+        // nothing is closer than the closest hit, and skipping past it finds
+        // whatever is behind
+        assert_eq!(group.occluded(&ray, T_MIN, T_MAX), expected.is_some());
+        if let Some(t) = expected {
+            assert!(!group.occluded(&ray, T_MIN, 0.999 * t));
+            assert!(group.hit(&ray, T_MIN, 0.999 * t).is_none());
+            if let Some(hit) = group.hit(&ray, t + 1e-6, T_MAX) {
+                assert!(hit.t > t);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parallel_build() {
+    // enough boxes for the top levels to be built on several threads
+    let bbs: Vec<AABB> = (0..20000)
+        .map(|_| {
+            let start = Vec3::rand(-100., 100.);
+            AABB::new(start, start + Vec3::rand(0., 1.))
+        })
+        .collect();
+    let (bvh, order) = Bvh::build(&bbs);
+
+    let mut seen = order.clone();
+    seen.sort_unstable();
+    assert!(seen.iter().copied().eq(0..bbs.len()));
+
+    // every leaf bounds the items it covers and the leaves cover them all
+    let mut covered = 0;
+    for node in bvh.nodes.iter().filter(|node| node.count > 0) {
+        for &i in &order[node.start..node.start + node.count] {
+            assert_eq!(node.bb.combine(&bbs[i]), node.bb);
+        }
+        covered += node.count;
+    }
+    assert_eq!(covered, bbs.len());
+
+    assert_eq!(bvh.stats.nodes, bvh.nodes.len());
+    assert_eq!(bvh.stats.nodes, 2 * bvh.stats.leaves - 1);
+    assert!(bvh.stats.depth >= 14);
+}
+
+#[test]
+fn test_refit() {
+    use std::sync::Arc;
+
+    use crate::material::*;
+    use crate::object::*;
+
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: crate::Color::white(),
+    });
+    let sphere_at = |c: Point3| Box::new(Sphere::new(c, 0.5, crate::Color::white(), mat.clone())) as Box<dyn Object>;
+    let mut centers: Vec<Point3> = (0..100).map(|_| Vec3::rand(-10., 10.)).collect();
+    let mut group = ObjectGroup::create_hierarchy(centers.iter().map(|c| sphere_at(*c)).collect());
+
+    let check = |group: &ObjectGroup, centers: &[Point3]| {
+        for _ in 0..200 {
+            let ray = Ray::new(Vec3::rand(-15., 15.), Vec3::rand(-1., 1.));
+            let expected = centers
+                .iter()
+                .filter_map(|c| sphere_at(*c).hit(&ray, T_MIN, T_MAX))
+                .map(|hit| hit.t)
+                .fold(None, |acc: Option<f64>, t| Some(acc.map_or(t, |acc| acc.min(t))));
+            assert_eq!(group.hit(&ray, T_MIN, T_MAX).map(|hit| hit.t), expected);
+        }
+    };
+
+    // moving everything together leaves the tree as good as new
+    let offset = vec3![1., -2., 0.5];
+    for c in centers.iter_mut() {
+        *c = *c + offset;
+    }
+    assert!(!group.update(|id, obj| *obj = sphere_at(centers[id])));
+    check(&group, &centers);
+
+    // scattering them makes every leaf span the whole scene
+    for c in centers.iter_mut() {
+        *c = Vec3::rand(-10., 10.);
+    }
+    assert!(group.update(|id, obj| *obj = sphere_at(centers[id])));
+    check(&group, &centers);
+
+    // an unbounded object turning into a bounded one moves into the tree
+    let plane_mat = mat.clone();
+    let mut objects: Vec<Box<dyn Object>> = centers.iter().map(|c| sphere_at(*c)).collect();
+    objects.push(Box::new(crate::plane::Plane::new(vec3![0., 0., -50.], vec3![0., 0., 1.], plane_mat)));
+    let mut group = ObjectGroup::create_hierarchy(objects);
+    assert!(group.bounding_box().is_none());
+    let last = centers.len();
+    assert!(group.update(|id, obj| {
+        if id == last {
+            *obj = sphere_at(vec3![0., 0., -50.]);
+        }
+    }));
+    assert!(group.bounding_box().is_some());
+    centers.push(vec3![0., 0., -50.]);
+    check(&group, &centers);
+}
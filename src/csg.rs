@@ -0,0 +1,337 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::cuboid::*;
+use crate::material::*;
+use crate::object::*;
+use crate::quadric::*;
+use crate::ray::*;
+use crate::roots::*;
+use crate::vec3::*;
+use crate::*;
+
+// stretch of the ray inside a solid, hits carry the outward normal of the
+// surface rather than one facing the ray
+pub struct Span {
+    pub enter: RayHit,
+    pub exit: RayHit,
+}
+
+// closed objects that can list where a ray is inside them
+pub trait Solid: Object {
+    // disjoint spans sorted by t, over the whole line and not just T_MIN..T_MAX
+    fn spans(&self, ray: &Ray) -> Vec<Span>;
+}
+
+fn surface_hit(ray: &Ray, t: f64, outward: Vec3, mat: &Arc<dyn Material>) -> RayHit {
+    RayHit {
+        col: Color::white(),
+        point: ray.cast(t),
+        t,
+        normal: outward,
+        front_face: true,
+        bary: (0., 0.),
+        uv: (0., 0.),
+        mat: mat.clone(),
+    }
+}
+
+impl Solid for Sphere {
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let diff = ray.origin - self.center;
+        let (t0, t1) = match solve_quadratic(1., 2. * ray.dir.dot(&diff), diff.mag_squared() - self.r * self.r) {
+            Some(roots) => roots,
+            None => return vec![],
+        };
+
+        let outward = |t: f64| (ray.cast(t) - self.center) / self.r;
+        vec![Span {
+            enter: surface_hit(ray, t0, outward(t0), &self.mat),
+            exit: surface_hit(ray, t1, outward(t1), &self.mat),
+        }]
+    }
+}
+
+impl Solid for Cuboid {
+    // slab test keeping track of which face bounds each end
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let (lo, hi) = self.extent();
+        let mut enter = (f64::NEG_INFINITY, 0);
+        let mut exit = (f64::INFINITY, 0);
+
+        for a in 0..3 {
+            if ray.dir[a].abs() < 1e-12 {
+                if ray.origin[a] < lo[a] || ray.origin[a] > hi[a] {
+                    return vec![];
+                }
+                continue;
+            }
+
+            // faces are ordered -x, +x, -y, +y, -z, +z
+            let t_lo = (lo[a] - ray.origin[a]) / ray.dir[a];
+            let t_hi = (hi[a] - ray.origin[a]) / ray.dir[a];
+            let (near, far) = if t_lo < t_hi {
+                ((t_lo, 2 * a), (t_hi, 2 * a + 1))
+            } else {
+                ((t_hi, 2 * a + 1), (t_lo, 2 * a))
+            };
+            if near.0 > enter.0 {
+                enter = near;
+            }
+            if far.0 < exit.0 {
+                exit = far;
+            }
+        }
+
+        if enter.0 > exit.0 {
+            return vec![];
+        }
+
+        let face_hit = |(t, face): (f64, usize)| {
+            surface_hit(ray, t, self.faces[face].normal(), &self.faces[face].mat)
+        };
+        vec![Span {
+            enter: face_hit(enter),
+            exit: face_hit(exit),
+        }]
+    }
+}
+
+impl Solid for Cylinder {
+    // only capped, fully swept cylinders are closed
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        debug_assert!(
+            self.capped && self.phi_max >= 2. * PI - 1e-9,
+            "csg needs a capped cylinder swept all the way round"
+        );
+        let o = ray.origin - self.center;
+        let d = ray.dir;
+        let r2 = self.radius * self.radius;
+        let side = |t: f64| {
+            let p = o + d * t;
+            vec3![p.x(), p.y(), 0.] / self.radius
+        };
+
+        // inside the infinite tube, each end keeps its t and outward normal
+        let a = d.x() * d.x() + d.y() * d.y();
+        let (mut enter, mut exit) = if a < 1e-12 {
+            if o.x() * o.x() + o.y() * o.y() > r2 {
+                return vec![];
+            }
+            // running along the axis, the caps will bound both ends
+            ((f64::NEG_INFINITY, Vec3::empty()), (f64::INFINITY, Vec3::empty()))
+        } else {
+            match solve_quadratic(a, 2. * (o.x() * d.x() + o.y() * d.y()), o.x() * o.x() + o.y() * o.y() - r2) {
+                Some((t0, t1)) => ((t0, side(t0)), (t1, side(t1))),
+                None => return vec![],
+            }
+        };
+
+        // and between the caps
+        if d.z().abs() < 1e-12 {
+            if o.z() < 0. || o.z() > self.height {
+                return vec![];
+            }
+        } else {
+            let bottom = (-o.z() / d.z(), vec3![0., 0., -1.]);
+            let top = ((self.height - o.z()) / d.z(), vec3![0., 0., 1.]);
+            let (near, far) = if bottom.0 < top.0 { (bottom, top) } else { (top, bottom) };
+            if near.0 > enter.0 {
+                enter = near;
+            }
+            if far.0 < exit.0 {
+                exit = far;
+            }
+        }
+
+        if enter.0 > exit.0 {
+            return vec![];
+        }
+
+        vec![Span {
+            enter: surface_hit(ray, enter.0, enter.1, &self.mat),
+            exit: surface_hit(ray, exit.0, exit.1, &self.mat),
+        }]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    // a with b cut out of it
+    Difference,
+}
+
+impl CsgOp {
+    fn contains(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+pub struct Csg {
+    pub op: CsgOp,
+    pub a: Arc<dyn Solid>,
+    pub b: Arc<dyn Solid>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Self {
+        Self { op, a, b }
+    }
+
+    pub fn union(a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Self {
+        Self::new(CsgOp::Union, a, b)
+    }
+
+    pub fn intersection(a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Self {
+        Self::new(CsgOp::Intersection, a, b)
+    }
+
+    pub fn difference(a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Self {
+        Self::new(CsgOp::Difference, a, b)
+    }
+}
+
+impl Solid for Csg {
+    // walks the boundaries of both children in order and keeps the ones
+    // where being inside the result changes
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let mut events = Vec::new();
+        for (from_a, spans) in [(true, self.a.spans(ray)), (false, self.b.spans(ray))] {
+            for span in spans {
+                events.push((from_a, true, span.enter));
+                events.push((from_a, false, span.exit));
+            }
+        }
+        events.sort_by(|x, y| x.2.t.partial_cmp(&y.2.t).unwrap_or(std::cmp::Ordering::Equal));
+
+        let (mut in_a, mut in_b, mut inside) = (false, false, false);
+        let mut enter = None;
+        let mut spans = Vec::new();
+
+        for (from_a, entering, mut hit) in events {
+            if from_a {
+                in_a = entering;
+            } else {
+                in_b = entering;
+            }
+
+            let now = self.op.contains(in_a, in_b);
+            if now == inside {
+                continue;
+            }
+            inside = now;
+
+            // the result is outside b where b's surface bounds it
+            if self.op == CsgOp::Difference && !from_a {
+                hit.normal = -hit.normal;
+            }
+
+            if now {
+                enter = Some(hit);
+            } else if let Some(enter) = enter.take() {
+                spans.push(Span { enter, exit: hit });
+            }
+        }
+
+        spans
+    }
+}
+
+impl Object for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        if !self.bounding_box()?.hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        let mut hit = self
+            .spans(ray)
+            .into_iter()
+            .flat_map(|span| vec![span.enter, span.exit])
+            .find(|hit| (t_min..=t_max).contains(&hit.t))?;
+
+        // same orientation rules as Sphere
+        if hit.normal.dot(&ray.dir) > 0. {
+            hit.normal = -hit.normal;
+            hit.front_face = false;
+        }
+        Some(hit)
+    }
+
+    // skips orienting the normal, the spans themselves are still needed to
+    // know where the result starts
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let in_range = |t: f64| (t_min..=t_max).contains(&t);
+        self.bounding_box().is_some_and(|bb| bb.hit(ray, t_min, t_max))
+            && self
+                .spans(ray)
+                .iter()
+                .any(|span| in_range(span.enter.t) || in_range(span.exit.t))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let a = self.a.bounding_box();
+        match self.op {
+            CsgOp::Union => Some(a?.combine(&self.b.bounding_box()?)),
+            // the result never leaves a
+            CsgOp::Intersection | CsgOp::Difference => a,
+        }
+    }
+}
+
+#[test]
+fn test_csg() {
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    let red: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::of_rgb(1., 0., 0.),
+    });
+
+    let sphere = Arc::new(Sphere::new(vec3![0., 0., 0.], 1., Color::white(), mat.clone()));
+    // slab through the middle of the sphere along z
+    let cuboid = Arc::new(Cuboid::new(vec3![-2., -2., -0.25], vec3![2., 2., 0.25], red.clone()));
+    let down = vec3![0., 0., -1.];
+
+    let cut = Csg::difference(sphere.clone(), cuboid.clone());
+    let hit = cut.hit(&Ray::new(vec3![0., 0., 5.], down), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 4.).abs() < 1e-9);
+
+    // from inside the slab we see the cut face of the lower half, which faces up
+    let hit = cut.hit(&Ray::new(vec3![0., 0., 0.], down), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 0.25).abs() < 1e-9);
+    assert_eq!(hit.normal, vec3![0., 0., 1.]);
+    assert!(hit.front_face);
+    assert!(Arc::ptr_eq(&hit.mat, &red));
+
+    let both = Csg::intersection(sphere.clone(), cuboid.clone());
+    let hit = both.hit(&Ray::new(vec3![0., 0., 5.], down), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 4.75).abs() < 1e-9);
+    assert!(both.hit(&Ray::new(vec3![0., 5., 0.5], vec3![0., -1., 0.]), T_MIN, T_MAX).is_none());
+
+    let cylinder = Arc::new(Cylinder::new(vec3![0., 0., -3.], 0.5, 6., true, mat));
+    let union = Csg::union(sphere, cylinder);
+    let hit = union.hit(&Ray::new(vec3![0., 0., 5.], down), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 2.).abs() < 1e-9);
+    assert_eq!(union.spans(&Ray::new(vec3![0., 0., 5.], down)).len(), 1);
+
+    assert!(cut.occluded(&Ray::new(vec3![0., 0., 5.], down), T_MIN, T_MAX));
+    assert!(!cut.occluded(&Ray::new(vec3![0., 0., 5.], down), T_MIN, 3.9));
+    assert!(!both.occluded(&Ray::new(vec3![0., 5., 0.5], vec3![0., -1., 0.]), T_MIN, T_MAX));
+}
+
+#[test]
+#[should_panic]
+fn test_csg_rejects_open_cylinder() {
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    let open = Cylinder::new(vec3![0., 0., 0.], 1., 2., false, mat);
+    open.spans(&Ray::new(vec3![0., 0., 5.], vec3![0., 0., -1.]));
+}
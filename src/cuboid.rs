@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::quad::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+// axis aligned box between two opposite corners, wrap it in Transformed
+// to rotate it
+pub struct Cuboid {
+    // -x, +x, -y, +y, -z, +z, normals pointing out
+    pub faces: [Quad; 6],
+    lo: Point3,
+    hi: Point3,
+    bb: AABB,
+}
+
+impl Cuboid {
+    pub fn new(a: Point3, b: Point3, mat: Arc<dyn Material>) -> Self {
+        Self::with_faces(a, b, [mat.clone(), mat.clone(), mat.clone(), mat.clone(), mat.clone(), mat])
+    }
+
+    // one material per face, in the same order as faces
+    pub fn with_faces(a: Point3, b: Point3, mats: [Arc<dyn Material>; 6]) -> Self {
+        let lo = vec3![a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())];
+        let hi = vec3![a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())];
+
+        let dx = vec3![hi.x() - lo.x(), 0., 0.];
+        let dy = vec3![0., hi.y() - lo.y(), 0.];
+        let dz = vec3![0., 0., hi.z() - lo.z()];
+
+        let [m0, m1, m2, m3, m4, m5] = mats;
+        let faces = [
+            Quad::new(lo, dz, dy, m0),
+            Quad::new(vec3![hi.x(), lo.y(), lo.z()], dy, dz, m1),
+            Quad::new(lo, dx, dz, m2),
+            Quad::new(vec3![lo.x(), hi.y(), lo.z()], dz, dx, m3),
+            Quad::new(lo, dy, dx, m4),
+            Quad::new(vec3![lo.x(), lo.y(), hi.z()], dx, dy, m5),
+        ];
+
+        Self {
+            faces,
+            lo,
+            hi,
+            bb: AABB::from_points(&[lo, hi]),
+        }
+    }
+
+    // the minimum and maximum corners
+    pub fn extent(&self) -> (Point3, Point3) {
+        (self.lo, self.hi)
+    }
+}
+
+impl Object for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, mut t_max: f64) -> Option<RayHit> {
+        if !self.bb.hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        let mut closest = None;
+        for face in self.faces.iter() {
+            if let Some(hit) = face.hit(ray, t_min, t_max) {
+                t_max = hit.t;
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.bb.hit(ray, t_min, t_max) && self.faces.iter().any(|face| face.occluded(ray, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bb)
+    }
+}
+
+#[test]
+fn test_cuboid_hit() {
+    use crate::*;
+
+    let cuboid = Arc::new(Cuboid::new(
+        vec3![1., 1., 1.],
+        vec3![-1., -1., -1.],
+        Arc::new(Lambert {
+            albedo: Color::white(),
+        }),
+    ));
+
+    let hit = cuboid
+        .hit(&Ray::new(vec3![0.2, 5., 0.3], vec3![0., -1., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 4.).abs() < 1e-9);
+    assert_eq!(hit.normal, vec3![0., 1., 0.]);
+    assert!(hit.front_face);
+
+    let inside = cuboid
+        .hit(&Ray::new(vec3![0., 0., 0.], vec3![1., 0., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!(!inside.front_face);
+    assert_eq!(inside.normal, vec3![-1., 0., 0.]);
+
+    // a cube turned 45 degrees about z reaches out to sqrt(2) along x
+    let rotated = Transformed::new(cuboid, Mat4::rotate(&vec3![0., 0., 1.], 45.));
+    let hit = rotated
+        .hit(&Ray::new(vec3![5., 0.3, 0.], vec3![-1., 0., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - (5.3 - 2f64.sqrt())).abs() < 1e-9);
+    assert!((hit.normal - vec3![1., 1., 0.].unit_vec()).is_zero());
+    assert!((rotated.bounding_box().unwrap().end.x() - 2f64.sqrt()).abs() < 1e-3);
+}
@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::quadric::*;
+use crate::ray::*;
+use crate::roots::*;
+use crate::vec3::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CurveType {
+    // flat strip that always turns to face the ray, cheap and fine for hair
+    Ribbon,
+    // round tube, shaded and shadowed like a thin cylinder
+    Tube,
+}
+
+fn lerp(t: f64, a: Point3, b: Point3) -> Point3 {
+    (1. - t) * a + t * b
+}
+
+// https://en.wikipedia.org/wiki/Blossom_(functional)
+fn blossom(cp: &[Point3; 4], u0: f64, u1: f64, u2: f64) -> Point3 {
+    let a = [lerp(u0, cp[0], cp[1]), lerp(u0, cp[1], cp[2]), lerp(u0, cp[2], cp[3])];
+    let b = [lerp(u1, a[0], a[1]), lerp(u1, a[1], a[2])];
+    lerp(u2, b[0], b[1])
+}
+
+// control points of the part of the curve between u0 and u1
+fn sub_curve(cp: &[Point3; 4], u0: f64, u1: f64) -> [Point3; 4] {
+    [
+        blossom(cp, u0, u0, u0),
+        blossom(cp, u0, u0, u1),
+        blossom(cp, u0, u1, u1),
+        blossom(cp, u1, u1, u1),
+    ]
+}
+
+// point and derivative at u
+fn eval(cp: &[Point3; 4], u: f64) -> (Point3, Vec3) {
+    let a = [lerp(u, cp[0], cp[1]), lerp(u, cp[1], cp[2]), lerp(u, cp[2], cp[3])];
+    let b = [lerp(u, a[0], a[1]), lerp(u, a[1], a[2])];
+    (lerp(u, b[0], b[1]), 3. * (b[1] - b[0]))
+}
+
+// cubic bezier strand whose width goes linearly from width.0 to width.1. long
+// strands should be split() so each piece gets its own tight bounding box
+pub struct Curve {
+    pub cp: [Point3; 4],
+    pub width: (f64, f64),
+    pub kind: CurveType,
+    pub mat: Arc<dyn Material>,
+
+    // the part of the full strand this piece covers, for widths and uvs
+    u: (f64, f64),
+    bb: AABB,
+}
+
+unsafe impl Sync for Curve {}
+unsafe impl Send for Curve {}
+
+impl Curve {
+    pub fn new(cp: [Point3; 4], width: (f64, f64), kind: CurveType, mat: Arc<dyn Material>) -> Self {
+        Self::segment(cp, width, (0., 1.), kind, mat)
+    }
+
+    fn segment(cp: [Point3; 4], width: (f64, f64), u: (f64, f64), kind: CurveType, mat: Arc<dyn Material>) -> Self {
+        // the curve stays inside the hull of its control points
+        let radius = 0.5 * width.0.max(width.1);
+        let hull = AABB::from_points(&sub_curve(&cp, u.0, u.1));
+        let bb = AABB::new(
+            hull.start - Vec3::of_scalar(radius),
+            hull.end + Vec3::of_scalar(radius),
+        );
+
+        Self {
+            cp,
+            width,
+            kind,
+            mat,
+            u,
+            bb,
+        }
+    }
+
+    // cuts the curve into n pieces of equal parameter length
+    pub fn split(&self, n: usize) -> Vec<Curve> {
+        let (u0, u1) = self.u;
+        (0..n)
+            .map(|i| {
+                let lo = u0 + (u1 - u0) * i as f64 / n as f64;
+                let hi = u0 + (u1 - u0) * (i + 1) as f64 / n as f64;
+                Self::segment(self.cp, self.width, (lo, hi), self.kind, self.mat.clone())
+            })
+            .collect()
+    }
+
+    fn width_at(&self, u: f64) -> f64 {
+        (1. - u) * self.width.0 + u * self.width.1
+    }
+
+    // finds where the ray passes within half a width of the curve, working in
+    // a frame where the ray starts at the origin and runs down +z. the curve
+    // is halved until it is close enough to a line segment
+    // https://www.pbr-book.org/3ed-2018/Shapes/Curves
+    fn intersect(&self, ray: &Ray, t_min: f64, mut t_max: f64) -> Option<(f64, f64, f64)> {
+        let (x, y) = ray.dir.orthonormal_basis();
+        let to_ray = |p: &Point3| {
+            let p = *p - ray.origin;
+            vec3![p.dot(&x), p.dot(&y), p.dot(&ray.dir)]
+        };
+        let cp = sub_curve(&self.cp, self.u.0, self.u.1);
+        let cp = [to_ray(&cp[0]), to_ray(&cp[1]), to_ray(&cp[2]), to_ray(&cp[3])];
+
+        // enough splits that the segments deviate from the curve by less than
+        // a twentieth of its width
+        let mut l0: f64 = 0.;
+        for i in 0..2 {
+            let d = cp[i] - 2. * cp[i + 1] + cp[i + 2];
+            l0 = l0.max(d.x().abs()).max(d.y().abs()).max(d.z().abs());
+        }
+        let eps = 0.05 * self.width.0.max(self.width.1);
+        let depth = if l0 > 0. && eps > 0. {
+            ((std::f64::consts::SQRT_2 * 6. * l0 / (8. * eps)).ln() / 4f64.ln())
+                .round()
+                .clamp(0., 10.) as u32
+        } else {
+            0
+        };
+
+        self.recursive_intersect(&cp, self.u, depth, t_min, &mut t_max)
+    }
+
+    fn recursive_intersect(&self, cp: &[Vec3; 4], u: (f64, f64), depth: u32, t_min: f64, t_max: &mut f64) -> Option<(f64, f64, f64)> {
+        let radius = 0.5 * self.width_at(u.0).max(self.width_at(u.1));
+        let bb = AABB::from_points(cp);
+        if bb.start.x() - radius > 0.
+            || bb.end.x() + radius < 0.
+            || bb.start.y() - radius > 0.
+            || bb.end.y() + radius < 0.
+            || bb.end.z() + radius < t_min
+            || bb.start.z() - radius > *t_max
+        {
+            return None;
+        }
+
+        if depth > 0 {
+            let mid = 0.5 * (u.0 + u.1);
+            let halves = [
+                sub_curve(cp, 0., 0.5),
+                sub_curve(cp, 0.5, 1.),
+            ];
+            let first = self.recursive_intersect(&halves[0], (u.0, mid), depth - 1, t_min, t_max);
+            let second = self.recursive_intersect(&halves[1], (mid, u.1), depth - 1, t_min, t_max);
+            // the second half only hits if it beats the first
+            return second.or(first);
+        }
+
+        // the origin has to lie between the perpendiculars at both ends
+        let start = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        let end = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if start < 0. || end < 0. {
+            return None;
+        }
+
+        let seg = vec3![cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y(), 0.];
+        let len_sq = seg.mag_squared();
+        if len_sq == 0. {
+            return None;
+        }
+        let w = (-cp[0].x() * seg.x() - cp[0].y() * seg.y()) / len_sq;
+        let hit_u = (u.0 + w * (u.1 - u.0)).clamp(u.0, u.1);
+        let half_width = 0.5 * self.width_at(hit_u);
+
+        let (pc, dpdw) = eval(cp, w.clamp(0., 1.));
+        let dist_sq = pc.x() * pc.x() + pc.y() * pc.y();
+        if dist_sq > half_width * half_width || pc.z() < t_min || pc.z() > *t_max {
+            return None;
+        }
+
+        // which side of the curve the ray passes on, 0.5 is the center line
+        let side = dpdw.x() * -pc.y() + pc.x() * dpdw.y();
+        let offset = dist_sq.sqrt() / (2. * half_width);
+        let v = if side > 0. { 0.5 + offset } else { 0.5 - offset };
+
+        *t_max = pc.z();
+        Some((pc.z(), hit_u, v))
+    }
+}
+
+impl Object for Curve {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let (t, u, v) = self.intersect(ray, t_min, t_max)?;
+
+        let (center, tangent) = eval(&self.cp, u);
+        let tangent = tangent.unit_vec();
+        let across = |p: Vec3| p - p.dot(&tangent) * tangent;
+
+        match self.kind {
+            CurveType::Ribbon => {
+                let facing = -across(ray.dir);
+                if facing.is_zero() {
+                    return None;
+                }
+                Some(oriented_hit(ray, t, facing.unit_vec(), (u, v), &self.mat))
+            }
+            CurveType::Tube => {
+                // treat the curve as a straight cylinder near the hit to find
+                // where the ray actually meets its round surface. the near
+                // root only counts when it is just in front of the ribbon hit,
+                // the infinite cylinder can be met far away from the strand.
+                // never moving past the ribbon keeps hit and occluded in step
+                let radius = 0.5 * self.width_at(u);
+                let d = across(ray.dir);
+                let o = across(ray.origin - center);
+                let window = t - 2. * radius / d.mag()..=t;
+                let t = match solve_quadratic(d.mag_squared(), 2. * d.dot(&o), o.mag_squared() - radius * radius) {
+                    Some((t0, _)) if t0 >= t_min && window.contains(&t0) => t0,
+                    _ => t,
+                };
+
+                let outward = across(ray.cast(t) - center);
+                if outward.is_zero() {
+                    return None;
+                }
+                Some(oriented_hit(ray, t, outward.unit_vec(), (u, v), &self.mat))
+            }
+        }
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        match self.kind {
+            CurveType::Ribbon => self.intersect(ray, t_min, t_max).is_some(),
+            // the round surface sits slightly in front of the ribbon distance
+            CurveType::Tube => self.hit(ray, t_min, t_max).is_some(),
+        }
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bb)
+    }
+}
+
+#[test]
+fn test_curve_hit() {
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: crate::Color::white(),
+    });
+    let straight = [
+        vec3![-1., 0., 0.],
+        vec3![-1. / 3., 0., 0.],
+        vec3![1. / 3., 0., 0.],
+        vec3![1., 0., 0.],
+    ];
+    let down = |x: f64, y: f64| Ray::new(vec3![x, y, 5.], vec3![0., 0., -1.]);
+
+    let ribbon = Curve::new(straight, (0.2, 0.2), CurveType::Ribbon, mat.clone());
+    let hit = ribbon.hit(&down(0., 0.05), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 5.).abs() < 1e-9);
+    assert!((hit.normal - vec3![0., 0., 1.]).is_zero());
+    assert!((hit.uv.0 - 0.5).abs() < 1e-9);
+    assert!((hit.uv.1 - 0.25).abs() < 1e-9 || (hit.uv.1 - 0.75).abs() < 1e-9);
+    assert!(ribbon.hit(&down(0., 0.2), T_MIN, T_MAX).is_none());
+    assert!(ribbon.hit(&down(1.2, 0.), T_MIN, T_MAX).is_none());
+
+    let tube = Curve::new(straight, (0.2, 0.2), CurveType::Tube, mat.clone());
+    let hit = tube.hit(&down(0.3, 0.), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 4.9).abs() < 1e-9);
+    let hit = tube.hit(&down(0.3, 0.06), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - (5. - 0.08)).abs() < 1e-9);
+    assert!((hit.normal - vec3![0., 0.6, 0.8]).is_zero());
+
+    // tapering to a point
+    let tapered = Curve::new(straight, (0.4, 0.), CurveType::Ribbon, mat.clone());
+    assert!(tapered.hit(&down(-0.9, 0.15), T_MIN, T_MAX).is_some());
+    assert!(tapered.hit(&down(0.9, 0.15), T_MIN, T_MAX).is_none());
+
+    // arch peaking at (0, 0.75, 0)
+    let arch = [
+        vec3![-1., 0., 0.],
+        vec3![-1. / 3., 1., 0.],
+        vec3![1. / 3., 1., 0.],
+        vec3![1., 0., 0.],
+    ];
+    let arch = Curve::new(arch, (0.02, 0.02), CurveType::Ribbon, mat.clone());
+    let hit = arch.hit(&down(0., 0.755), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 5.).abs() < 1e-6);
+    assert!(arch.hit(&down(0., 0.5), T_MIN, T_MAX).is_none());
+
+    let pieces = arch.split(4);
+    let bb = arch.bounding_box().unwrap();
+    for piece in pieces.iter() {
+        let piece_bb = piece.bounding_box().unwrap();
+        assert!(piece_bb.end.y() - piece_bb.start.y() < bb.end.y() - bb.start.y());
+    }
+    let objects: Vec<Box<dyn Object>> = pieces.into_iter().map(|p| Box::new(p) as Box<dyn Object>).collect();
+    let group = ObjectGroup::create_hierarchy(objects);
+    let hit = group.hit(&down(0., 0.755), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 5.).abs() < 1e-6);
+    assert!((hit.uv.0 - 0.5).abs() < 1e-3);
+
+    // tube hits stay on the strand, so nothing is found in front of them
+    let wiggle = [
+        vec3![-1., 0., 0.],
+        vec3![-0.3, 1., 0.2],
+        vec3![0.3, -1., -0.2],
+        vec3![1., 0., 0.],
+    ];
+    let tube = Curve::new(wiggle, (0.3, 0.1), CurveType::Tube, mat);
+    for _ in 0..20000 {
+        let ray = Ray::new(Vec3::rand(-2., 2.), Vec3::rand(-1., 1.));
+        if let Some(hit) = tube.hit(&ray, T_MIN, T_MAX) {
+            assert!(!tube.occluded(&ray, T_MIN, 0.999 * hit.t));
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::triangle::*;
+use crate::vec3::*;
+
+// elevation samples on a regular nx * ny grid, x varies fastest
+pub struct HeightMap {
+    pub dims: (usize, usize),
+    pub data: Vec<f64>,
+}
+
+impl HeightMap {
+    pub fn new(dims: (usize, usize), data: Vec<f64>) -> Self {
+        assert_eq!(dims.0 * dims.1, data.len());
+        assert!(dims.0 > 1 && dims.1 > 1, "a height map needs at least 2x2 samples");
+        Self { dims, data }
+    }
+
+    pub fn at(&self, x: usize, y: usize) -> f64 {
+        self.data[y * self.dims.0 + x]
+    }
+}
+
+// one level of the min-max quadtree, each entry bounds the world space heights
+// of a square block of cells
+struct Level {
+    nx: usize,
+    ny: usize,
+    bounds: Vec<(f64, f64)>,
+}
+
+// terrain over the rectangle corner.xy to corner.xy + size.xy, sample heights
+// are scaled by size.z and offset by corner.z. every cell is split into two
+// triangles, found by walking a min-max quadtree over the grid
+pub struct Heightfield {
+    pub map: HeightMap,
+    pub corner: Point3,
+    pub size: Vec3,
+    pub mat: Arc<dyn Material>,
+
+    normals: Vec<Vec3>,
+    // levels[0] bounds single cells, the last level is one node over everything
+    levels: Vec<Level>,
+}
+
+unsafe impl Sync for Heightfield {}
+unsafe impl Send for Heightfield {}
+
+impl Heightfield {
+    pub fn new(map: HeightMap, corner: Point3, size: Vec3, mat: Arc<dyn Material>) -> Self {
+        assert!(size.x() > 0. && size.y() > 0.);
+        let mut field = Self {
+            map,
+            corner,
+            size,
+            mat,
+            normals: vec![],
+            levels: vec![],
+        };
+        field.build();
+        field
+    }
+
+    fn vertex(&self, x: usize, y: usize) -> Point3 {
+        let (nx, ny) = self.map.dims;
+        self.corner
+            + vec3![
+                self.size.x() * x as f64 / (nx - 1) as f64,
+                self.size.y() * y as f64 / (ny - 1) as f64,
+                self.size.z() * self.map.at(x, y)
+            ]
+    }
+
+    fn build(&mut self) {
+        let (nx, ny) = self.map.dims;
+
+        // central differences, one sided along the edges
+        let mut normals = Vec::with_capacity(nx * ny);
+        for y in 0..ny {
+            for x in 0..nx {
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(nx - 1));
+                let (y0, y1) = (y.saturating_sub(1), (y + 1).min(ny - 1));
+                let dx = self.vertex(x1, y) - self.vertex(x0, y);
+                let dy = self.vertex(x, y1) - self.vertex(x, y0);
+                normals.push(dx.cross(&dy).unit_vec());
+            }
+        }
+        self.normals = normals;
+
+        let (cx, cy) = (nx - 1, ny - 1);
+        let mut bounds = Vec::with_capacity(cx * cy);
+        for y in 0..cy {
+            for x in 0..cx {
+                let z = [
+                    self.vertex(x, y).z(),
+                    self.vertex(x + 1, y).z(),
+                    self.vertex(x, y + 1).z(),
+                    self.vertex(x + 1, y + 1).z(),
+                ];
+                let lo = z.iter().copied().fold(f64::INFINITY, f64::min);
+                let hi = z.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                bounds.push((lo, hi));
+            }
+        }
+
+        let mut levels = vec![Level {
+            nx: cx,
+            ny: cy,
+            bounds,
+        }];
+        loop {
+            let below = levels.last().unwrap();
+            if below.nx == 1 && below.ny == 1 {
+                break;
+            }
+
+            let (nx, ny) = (below.nx.div_ceil(2), below.ny.div_ceil(2));
+            let mut bounds = vec![(f64::INFINITY, f64::NEG_INFINITY); nx * ny];
+            for y in 0..below.ny {
+                for x in 0..below.nx {
+                    let (lo, hi) = below.bounds[y * below.nx + x];
+                    let parent = &mut bounds[(y / 2) * nx + x / 2];
+                    parent.0 = parent.0.min(lo);
+                    parent.1 = parent.1.max(hi);
+                }
+            }
+            levels.push(Level { nx, ny, bounds });
+        }
+        self.levels = levels;
+    }
+
+    // box around the cells covered by node (x, y) of the given level
+    fn node_bb(&self, level: usize, x: usize, y: usize) -> AABB {
+        let (nx, ny) = self.map.dims;
+        let (cx, cy) = (nx - 1, ny - 1);
+        let step_x = self.size.x() / cx as f64;
+        let step_y = self.size.y() / cy as f64;
+
+        let x0 = x << level;
+        let x1 = ((x + 1) << level).min(cx);
+        let y0 = y << level;
+        let y1 = ((y + 1) << level).min(cy);
+
+        let lvl = &self.levels[level];
+        let (lo, hi) = lvl.bounds[y * lvl.nx + x];
+
+        // padded so flat terrain still has some thickness
+        let pad = 0.0001;
+        AABB::new(
+            vec3![
+                self.corner.x() + x0 as f64 * step_x - pad,
+                self.corner.y() + y0 as f64 * step_y - pad,
+                lo - pad
+            ],
+            vec3![
+                self.corner.x() + x1 as f64 * step_x + pad,
+                self.corner.y() + y1 as f64 * step_y + pad,
+                hi + pad
+            ],
+        )
+    }
+
+    fn hit_cell(&self, x: usize, y: usize, ray: &Ray, t_min: f64, mut t_max: f64) -> Option<RayHit> {
+        let nx = self.map.dims.0;
+        let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+        let verts = corners.map(|(x, y)| self.vertex(x, y));
+        let normals = corners.map(|(x, y)| &self.normals[y * nx + x]);
+
+        let mut closest: Option<RayHit> = None;
+        for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+            let (t, u, v) = match intersect_triangle(ray, &verts[a], &verts[b], &verts[c], t_min, t_max) {
+                Some(found) => found,
+                None => continue,
+            };
+            t_max = t;
+
+            let point = ray.cast(t);
+            let uv = (
+                ((point.x() - self.corner.x()) / self.size.x()).clamp(0., 1.),
+                ((point.y() - self.corner.y()) / self.size.y()).clamp(0., 1.),
+            );
+            closest = Some(triangle_hit(
+                ray,
+                [&verts[a], &verts[b], &verts[c]],
+                Some([normals[a], normals[b], normals[c]]),
+                t,
+                (u, v),
+                uv,
+                &self.mat,
+            ));
+        }
+
+        closest
+    }
+}
+
+impl Object for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, mut t_max: f64) -> Option<RayHit> {
+        // children are pushed far to near so the nearest is visited first and
+        // its hit can prune the rest
+        let flip_x = if ray.dir.x() < 0. { 1 } else { 0 };
+        let flip_y = if ray.dir.y() < 0. { 1 } else { 0 };
+
+        let mut closest: Option<RayHit> = None;
+        let mut stack = vec![(self.levels.len() - 1, 0, 0)];
+
+        while let Some((level, x, y)) = stack.pop() {
+            if !self.node_bb(level, x, y).hit(ray, t_min, t_max) {
+                continue;
+            }
+
+            if level == 0 {
+                if let Some(hit) = self.hit_cell(x, y, ray, t_min, t_max) {
+                    t_max = hit.t;
+                    closest = Some(hit);
+                }
+                continue;
+            }
+
+            let below = &self.levels[level - 1];
+            for (dx, dy) in [(1, 1), (0, 1), (1, 0), (0, 0)] {
+                let (cx, cy) = (2 * x + (dx ^ flip_x), 2 * y + (dy ^ flip_y));
+                if cx < below.nx && cy < below.ny {
+                    stack.push((level - 1, cx, cy));
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let mut stack = vec![(self.levels.len() - 1, 0, 0)];
+
+        while let Some((level, x, y)) = stack.pop() {
+            if !self.node_bb(level, x, y).hit(ray, t_min, t_max) {
+                continue;
+            }
+
+            if level == 0 {
+                let v = [
+                    self.vertex(x, y),
+                    self.vertex(x + 1, y),
+                    self.vertex(x + 1, y + 1),
+                    self.vertex(x, y + 1),
+                ];
+                for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+                    if intersect_triangle(ray, &v[a], &v[b], &v[c], t_min, t_max).is_some() {
+                        return true;
+                    }
+                }
+                continue;
+            }
+
+            let below = &self.levels[level - 1];
+            for (cx, cy) in [(2 * x, 2 * y), (2 * x + 1, 2 * y), (2 * x, 2 * y + 1), (2 * x + 1, 2 * y + 1)] {
+                if cx < below.nx && cy < below.ny {
+                    stack.push((level - 1, cx, cy));
+                }
+            }
+        }
+
+        false
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.node_bb(self.levels.len() - 1, 0, 0))
+    }
+}
+
+#[test]
+fn test_heightfield_matches_triangles() {
+    use crate::Color;
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    let dims = (13, 9);
+    let data = (0..dims.0 * dims.1).map(|_| rng.gen_range(0.0..1.)).collect();
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    let field = Heightfield::new(
+        HeightMap::new(dims, data),
+        vec3![-2., -1., 0.],
+        vec3![4., 3., 0.5],
+        mat,
+    );
+
+    for _ in 0..500 {
+        let origin = vec3![
+            rng.gen_range(-3.0..3.),
+            rng.gen_range(-3.0..3.),
+            rng.gen_range(1.0..3.)
+        ];
+        let target = vec3![rng.gen_range(-2.5..2.5), rng.gen_range(-1.5..2.5), 0.];
+        let ray = Ray::new(origin, target - origin);
+
+        let mut expected: Option<f64> = None;
+        for y in 0..dims.1 - 1 {
+            for x in 0..dims.0 - 1 {
+                let v = [
+                    field.vertex(x, y),
+                    field.vertex(x + 1, y),
+                    field.vertex(x + 1, y + 1),
+                    field.vertex(x, y + 1),
+                ];
+                for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+                    if let Some((t, _, _)) = intersect_triangle(&ray, &v[a], &v[b], &v[c], T_MIN, T_MAX) {
+                        expected = Some(expected.map_or(t, |e| e.min(t)));
+                    }
+                }
+            }
+        }
+
+        let got = field.hit(&ray, T_MIN, T_MAX).map(|hit| hit.t);
+        assert_eq!(field.occluded(&ray, T_MIN, T_MAX), expected.is_some());
+        match (got, expected) {
+            (Some(got), Some(expected)) => assert!((got - expected).abs() < 1e-9),
+            (None, None) => {}
+            _ => panic!("{:?} vs {:?}", got, expected),
+        }
+    }
+
+    // a flat field reports straight up normals and uvs across the whole grid
+    let flat = Heightfield::new(
+        HeightMap::new((3, 3), vec![0.5; 9]),
+        vec3![0., 0., 0.],
+        vec3![2., 2., 1.],
+        Arc::new(Lambert {
+            albedo: Color::white(),
+        }),
+    );
+    let hit = flat
+        .hit(&Ray::new(vec3![1.5, 0.5, 2.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 1.5).abs() < 1e-9);
+    assert!((hit.normal - vec3![0., 0., 1.]).is_zero());
+    assert!((hit.uv.0 - 0.75).abs() < 1e-9);
+    assert!((hit.uv.1 - 0.25).abs() < 1e-9);
+}
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::bvh::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+// one placement of a shared bottom level hierarchy. copies only cost a
+// transform each, the geometry itself is stored once
+pub struct Instance {
+    pub blas: Arc<ObjectGroup>,
+    pub xform: Mat4,
+    inv: Mat4,
+    // None when the shared geometry is unbounded, like a plane
+    bb: Option<AABB>,
+}
+
+impl Instance {
+    pub fn new(blas: Arc<ObjectGroup>, xform: Mat4) -> Self {
+        let inv = xform.inverse().expect("transform must be invertible");
+        let bb = blas.bounding_box().map(|bb| transform_bb(&bb, &xform));
+        Self { blas, xform, inv, bb }
+    }
+}
+
+impl Object for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        hit_transformed(self.blas.as_ref(), &self.inv, ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        // same interval as hit_transformed
+        let (local, scale) = local_ray(&self.inv, ray);
+        self.blas.occluded(&local, t_min * scale, t_max * scale)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bb
+    }
+}
+
+// top level hierarchy over instances. rays are moved into each instance's
+// object space and continue down its bottom level hierarchy, so memory grows
+// with the unique geometry rather than the number of copies
+pub struct InstanceGroup {
+    group: ObjectGroup,
+}
+
+impl InstanceGroup {
+    pub fn new(instances: Vec<Instance>) -> Self {
+        let objects = instances
+            .into_iter()
+            .map(|inst| Box::new(inst) as Box<dyn Object>)
+            .collect();
+        Self {
+            group: ObjectGroup::create_hierarchy(objects),
+        }
+    }
+
+    pub fn stats(&self) -> &BuildStats {
+        self.group.stats()
+    }
+}
+
+impl Object for InstanceGroup {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        self.group.hit(ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.group.occluded(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.group.bounding_box()
+    }
+}
+
+#[test]
+fn test_instances_match_transformed() {
+    use rand::Rng;
+
+    use crate::material::*;
+    use crate::Color;
+
+    let mut rng = rand::thread_rng();
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    // a small cluster of spheres standing in for a tree
+    let blas = Arc::new(ObjectGroup::create_hierarchy(
+        (0..8)
+            .map(|_| Box::new(Sphere::new(Vec3::rand(-1., 1.), 0.5, Color::white(), mat.clone())) as Box<dyn Object>)
+            .collect(),
+    ));
+
+    let xforms: Vec<Mat4> = (0..100)
+        .map(|_| {
+            Mat4::translate(&Vec3::rand(-20., 20.))
+                * Mat4::rotate(&Vec3::rand(-1., 1.), rng.gen_range(0. ..360.))
+                * Mat4::scale(&Vec3::of_scalar(rng.gen_range(0.5..2.)))
+        })
+        .collect();
+    let group = InstanceGroup::new(xforms.iter().map(|x| Instance::new(blas.clone(), *x)).collect());
+    // the geometry is shared, not copied
+    assert_eq!(Arc::strong_count(&blas), xforms.len() + 1);
+
+    let flat = ObjectGroup::create_hierarchy(
+        xforms
+            .iter()
+            .map(|x| Box::new(Transformed::new(blas.clone(), *x)) as Box<dyn Object>)
+            .collect(),
+    );
+    for _ in 0..500 {
+        let ray = Ray::new(Vec3::rand(-25., 25.), Vec3::rand(-1., 1.));
+        let expected = flat.hit(&ray, T_MIN, T_MAX);
+        let got = group.hit(&ray, T_MIN, T_MAX);
+        assert_eq!(got.is_some(), expected.is_some());
+        if let (Some(got), Some(expected)) = (got, expected) {
+            assert!((got.t - expected.t).abs() < 1e-9);
+            assert!((got.normal - expected.normal).is_zero());
+            assert!(!group.occluded(&ray, T_MIN, 0.999 * got.t));
+        }
+        assert_eq!(group.occluded(&ray, T_MIN, T_MAX), flat.occluded(&ray, T_MIN, T_MAX));
+    }
+
+}
+
+#[test]
+fn test_unbounded_instances() {
+    use crate::material::*;
+    use crate::plane::Plane;
+    use crate::Color;
+
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    // a ball sitting on a ground plane, shared by every instance
+    let blas = Arc::new(ObjectGroup::create_hierarchy(vec![
+        Box::new(Sphere::new(vec3![0., 0., 1.], 1., Color::white(), mat.clone())),
+        Box::new(Plane::new(vec3![0., 0., 0.], vec3![0., 0., 1.], mat)),
+    ]));
+    let group = InstanceGroup::new(vec![
+        Instance::new(blas.clone(), Mat4::identity()),
+        Instance::new(blas, Mat4::translate(&vec3![0., 0., -10.])),
+    ]);
+    assert!(group.bounding_box().is_none());
+
+    let hit = group.hit(&Ray::new(vec3![0., 0., 5.], vec3![0., 0., -1.]), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 3.).abs() < 1e-9);
+    // far from the ball only the upper plane is seen
+    let hit = group.hit(&Ray::new(vec3![50., 0., 5.], vec3![0., 0., -1.]), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 5.).abs() < 1e-9);
+    assert!(!group.occluded(&Ray::new(vec3![50., 0., 5.], vec3![0., 0., -1.]), T_MIN, 4.9));
+}
@@ -0,0 +1,4 @@
+pub mod obj;
+pub mod ply;
+pub mod voxel;
+pub mod heightmap;
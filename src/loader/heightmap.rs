@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::heightfield::*;
+
+// grayscale netpbm images, ascii (P2, P3) or binary (P5, P6) with 8 or 16 bit
+// samples. colour images are averaged down to gray, values are scaled to 0..1
+// and the top row of the image ends up at the far (+y) edge of the map
+pub fn load_heightmap(path: impl AsRef<Path>) -> Result<HeightMap> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    parse_pnm(&data, &path.display().to_string())
+}
+
+pub fn parse_pnm(data: &[u8], file: &str) -> Result<HeightMap> {
+    let mut pos = 0;
+
+    // header tokens are separated by whitespace, comments run to the end of the line
+    let mut token = || -> Option<String> {
+        loop {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < data.len() && data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&data[start..pos]).into_owned())
+    };
+
+    let magic = token().ok_or_else(|| anyhow!("{}: empty file", file))?;
+    let (channels, binary) = match magic.as_str() {
+        "P2" => (1, false),
+        "P3" => (3, false),
+        "P5" => (1, true),
+        "P6" => (3, true),
+        _ => bail!("{}: unsupported image type `{}`, expected P2, P3, P5 or P6", file, magic),
+    };
+
+    let mut number = |what: &str| -> Result<usize> {
+        let tok = token().ok_or_else(|| anyhow!("{}: missing {}", file, what))?;
+        match tok.parse() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => bail!("{}: invalid {} `{}`", file, what, tok),
+        }
+    };
+    let width = number("width")?;
+    let height = number("height")?;
+    let maxval = number("maxval")?;
+    if maxval > 65535 {
+        bail!("{}: maxval {} is out of range", file, maxval);
+    }
+
+    let count = sample_count((width, height), channels, file)?;
+    let samples: Vec<f64> = if binary {
+        // exactly one whitespace byte separates the header from the raster
+        let start = pos + 1;
+        let size = if maxval > 255 { 2 } else { 1 };
+        let bytes = count * size;
+        let raster = data.get(start..).and_then(|rest| rest.get(..bytes)).ok_or_else(|| {
+            anyhow!("{}: expected {} bytes of image data, found {}", file, bytes, data.len().saturating_sub(start))
+        })?;
+
+        if size == 2 {
+            raster
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64)
+                .collect()
+        } else {
+            raster.iter().map(|&b| b as f64).collect()
+        }
+    } else {
+        // the header can claim more samples than the file holds
+        let mut samples = Vec::new();
+        while let Some(tok) = token() {
+            let value: usize = tok
+                .parse()
+                .map_err(|_| anyhow!("{}: invalid sample `{}`", file, tok))?;
+            samples.push(value as f64);
+        }
+        if samples.len() != count {
+            bail!("{}: expected {} samples, found {}", file, count, samples.len());
+        }
+        samples
+    };
+
+    let mut heights = vec![0.; width * height];
+    for row in 0..height {
+        for x in 0..width {
+            let pixel = &samples[(row * width + x) * channels..][..channels];
+            let gray = pixel.iter().sum::<f64>() / channels as f64;
+            heights[(height - 1 - row) * width + x] = gray / maxval as f64;
+        }
+    }
+
+    Ok(HeightMap::new((width, height), heights))
+}
+
+// number of values in a dims grid with per_sample values each, rejecting
+// maps too small to triangulate or too large to index. with per_sample at
+// most 4 the byte size fits too
+fn sample_count(dims: (usize, usize), per_sample: usize, file: &str) -> Result<usize> {
+    if dims.0 < 2 || dims.1 < 2 {
+        bail!("{}: a height map needs at least 2x2 samples, found {}x{}", file, dims.0, dims.1);
+    }
+    dims.0
+        .checked_mul(dims.1)
+        .and_then(|n| n.checked_mul(per_sample))
+        .filter(|n| n.checked_mul(4).is_some())
+        .ok_or_else(|| anyhow!("{}: image dimensions {}x{} are too large", file, dims.0, dims.1))
+}
+
+// raw grids are bare little endian f32 heights, x varying fastest
+pub fn load_heightmap_raw(path: impl AsRef<Path>, dims: (usize, usize)) -> Result<HeightMap> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let expected = sample_count(dims, 1, &file)? * 4;
+    let bytes = std::fs::read(path).with_context(|| format!("failed to read {}", file))?;
+
+    if bytes.len() != expected {
+        bail!(
+            "{}: expected {} bytes for a {}x{} grid, found {}",
+            file,
+            expected,
+            dims.0,
+            dims.1,
+            bytes.len()
+        );
+    }
+
+    let data = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+        .collect();
+
+    Ok(HeightMap::new(dims, data))
+}
+
+#[test]
+fn test_parse_pnm() {
+    let ascii = parse_pnm(b"P2\n# a comment\n3 2\n10\n0 5 10\n1 2 3\n", "test.pgm").unwrap();
+    assert_eq!(ascii.dims, (3, 2));
+    // rows are flipped so the image top is at +y
+    assert_eq!(ascii.data, vec![0.1, 0.2, 0.3, 0., 0.5, 1.]);
+
+    let mut binary = b"P6 2 2 255\n".to_vec();
+    binary.extend([0, 0, 0, 255, 255, 255, 30, 60, 90, 3, 3, 3]);
+    let color = parse_pnm(&binary, "test.ppm").unwrap();
+    assert_eq!(color.data, vec![60. / 255., 3. / 255., 0., 1.]);
+
+    let mut wide = b"P5 2 2 1000\n".to_vec();
+    for v in [0u16, 250, 500, 1000] {
+        wide.extend(v.to_be_bytes());
+    }
+    assert_eq!(parse_pnm(&wide, "test.pgm").unwrap().data, vec![0.5, 1., 0., 0.25]);
+
+    assert!(parse_pnm(b"P5 2 2 255\n\x00\x01", "short.pgm").is_err());
+    assert!(parse_pnm(b"P4 2 2\n", "bitmap.pbm").is_err());
+
+    // oversized dimensions are errors rather than overflow panics
+    assert!(parse_pnm(b"P5 4294967296 4294967297 255\n", "huge.pgm").is_err());
+    assert!(parse_pnm(b"P3 4294967296 4294967296 255\n", "huge.ppm").is_err());
+    let err = load_heightmap_raw("does-not-exist.raw", (usize::MAX, 2)).err().unwrap();
+    assert!(err.to_string().contains("too large"));
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::material::*;
+use crate::mesh::*;
+use crate::vec3::*;
+use crate::*;
+
+// a run of faces sharing one group and one material
+pub struct ObjGroup {
+    pub name: String,
+    pub material: String,
+    pub mesh: TriangleMesh,
+}
+
+// indices into the position, uv and normal lists
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct GroupBuilder {
+    name: String,
+    material: String,
+    faces: Vec<[Corner; 3]>,
+}
+
+pub fn load_obj(path: impl AsRef<Path>) -> Result<Vec<ObjGroup>> {
+    let path = path.as_ref();
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    parse_obj(&src, &path.display().to_string(), dir)
+}
+
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<HashMap<String, Arc<dyn Material>>> {
+    let path = path.as_ref();
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    parse_mtl(&src, &path.display().to_string())
+}
+
+fn default_material() -> Arc<dyn Material> {
+    Arc::new(Lambert {
+        albedo: Color::of_rgb(0.8, 0.8, 0.8),
+    })
+}
+
+fn floats(args: &[&str], min: usize, max: usize, file: &str, line_no: usize) -> Result<Vec<f64>> {
+    if args.len() < min || args.len() > max {
+        bail!("{}:{}: expected {} to {} numbers, got {}", file, line_no, min, max, args.len());
+    }
+    args.iter()
+        .map(|a| {
+            a.parse::<f64>()
+                .map_err(|_| anyhow!("{}:{}: invalid number `{}`", file, line_no, a))
+        })
+        .collect()
+}
+
+// obj indices are 1 based, negative ones count back from the latest element
+fn resolve_index(s: &str, len: usize, file: &str, line_no: usize) -> Result<usize> {
+    let i: i64 = s
+        .parse()
+        .map_err(|_| anyhow!("{}:{}: invalid index `{}`", file, line_no, s))?;
+
+    let resolved = if i > 0 { i - 1 } else { len as i64 + i };
+    if i == 0 || resolved < 0 || resolved >= len as i64 {
+        bail!("{}:{}: index {} out of range (have {})", file, line_no, i, len);
+    }
+    Ok(resolved as usize)
+}
+
+pub fn parse_obj(src: &str, file: &str, dir: &Path) -> Result<Vec<ObjGroup>> {
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+
+    let mut groups = vec![GroupBuilder {
+        name: String::from("default"),
+        material: String::new(),
+        faces: Vec::new(),
+    }];
+
+    for (i, line) in src.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args: Vec<&str> = parts.collect();
+
+        match keyword {
+            "v" => {
+                let v = floats(&args, 3, 4, file, line_no)?;
+                positions.push(vec3![v[0], v[1], v[2]]);
+            }
+            "vn" => {
+                let v = floats(&args, 3, 3, file, line_no)?;
+                normals.push(vec3![v[0], v[1], v[2]].unit_vec());
+            }
+            "vt" => {
+                let v = floats(&args, 1, 3, file, line_no)?;
+                uvs.push((v[0], v.get(1).copied().unwrap_or(0.)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    bail!("{}:{}: face needs at least 3 vertices", file, line_no);
+                }
+
+                let corners = args
+                    .iter()
+                    .map(|arg| {
+                        let mut refs = arg.split('/');
+                        let v = resolve_index(refs.next().unwrap_or(""), positions.len(), file, line_no)?;
+                        let vt = match refs.next() {
+                            Some("") | None => None,
+                            Some(s) => Some(resolve_index(s, uvs.len(), file, line_no)?),
+                        };
+                        let vn = match refs.next() {
+                            Some("") | None => None,
+                            Some(s) => Some(resolve_index(s, normals.len(), file, line_no)?),
+                        };
+                        if refs.next().is_some() {
+                            bail!("{}:{}: malformed face vertex `{}`", file, line_no, arg);
+                        }
+                        Ok((v, vt, vn))
+                    })
+                    .collect::<Result<Vec<Corner>>>()?;
+
+                // fan triangulation around the first corner
+                let group = groups.last_mut().unwrap();
+                for k in 1..corners.len() - 1 {
+                    group.faces.push([corners[0], corners[k], corners[k + 1]]);
+                }
+            }
+            "g" | "o" => {
+                let name = if args.is_empty() {
+                    String::from("default")
+                } else {
+                    args.join(" ")
+                };
+                let material = groups.last().unwrap().material.clone();
+                groups.push(GroupBuilder {
+                    name,
+                    material,
+                    faces: Vec::new(),
+                });
+            }
+            "usemtl" => {
+                if args.len() != 1 {
+                    bail!("{}:{}: usemtl expects a material name", file, line_no);
+                }
+                if !materials.contains_key(args[0]) {
+                    bail!("{}:{}: unknown material `{}`", file, line_no, args[0]);
+                }
+                let name = groups.last().unwrap().name.clone();
+                groups.push(GroupBuilder {
+                    name,
+                    material: args[0].to_string(),
+                    faces: Vec::new(),
+                });
+            }
+            "mtllib" => {
+                for lib in args {
+                    let lib = load_mtl(dir.join(lib))
+                        .with_context(|| format!("{}:{}: failed to load mtllib", file, line_no))?;
+                    materials.extend(lib);
+                }
+            }
+            // smoothing groups, lines, points and free-form geometry aren't rendered
+            _ => {}
+        }
+    }
+
+    let groups = groups
+        .into_iter()
+        .filter(|g| !g.faces.is_empty())
+        .map(|g| {
+            let mat = materials
+                .get(&g.material)
+                .cloned()
+                .unwrap_or_else(default_material);
+            let mesh = build_mesh(&g.faces, &positions, &uvs, &normals, mat);
+            ObjGroup {
+                name: g.name,
+                material: g.material,
+                mesh,
+            }
+        })
+        .collect();
+
+    Ok(groups)
+}
+
+// obj indexes positions, uvs and normals separately, meshes share one index
+// so every distinct corner becomes its own vertex
+fn build_mesh(
+    faces: &[[Corner; 3]],
+    positions: &[Point3],
+    uvs: &[(f64, f64)],
+    normals: &[Vec3],
+    mat: Arc<dyn Material>,
+) -> TriangleMesh {
+    let has_uvs = faces.iter().flatten().all(|c| c.1.is_some());
+    let has_normals = faces.iter().flatten().all(|c| c.2.is_some());
+
+    let mut remap: HashMap<Corner, usize> = HashMap::new();
+    let mut mesh_vertices = Vec::new();
+    let mut mesh_uvs = Vec::new();
+    let mut mesh_normals = Vec::new();
+
+    let indices = faces
+        .iter()
+        .map(|face| {
+            let mut tri = [0; 3];
+            for (k, corner) in face.iter().enumerate() {
+                let key = (
+                    corner.0,
+                    corner.1.filter(|_| has_uvs),
+                    corner.2.filter(|_| has_normals),
+                );
+                tri[k] = *remap.entry(key).or_insert_with(|| {
+                    mesh_vertices.push(positions[key.0]);
+                    if let Some(vt) = key.1 {
+                        mesh_uvs.push(uvs[vt]);
+                    }
+                    if let Some(vn) = key.2 {
+                        mesh_normals.push(normals[vn]);
+                    }
+                    mesh_vertices.len() - 1
+                });
+            }
+            tri
+        })
+        .collect();
+
+    TriangleMesh::new(mesh_vertices, mesh_normals, mesh_uvs, indices, mat)
+}
+
+#[derive(Default)]
+struct MtlEntry {
+    kd: Option<Color>,
+    ks: Option<Color>,
+    ke: Option<Color>,
+    ni: Option<f64>,
+    ns: Option<f64>,
+    illum: Option<i64>,
+}
+
+impl MtlEntry {
+    fn into_material(self) -> Arc<dyn Material> {
+        let is_black = |c: &Color| c.r <= 0. && c.g <= 0. && c.b <= 0.;
+
+        if let Some(ke) = self.ke.filter(|c| !is_black(c)) {
+            return Arc::new(DiffuseLight { col: ke });
+        }
+
+        match self.illum {
+            // refraction models
+            Some(4) | Some(6) | Some(7) | Some(9) => Arc::new(Glass {
+                refraction_index: self.ni.unwrap_or(1.5),
+            }),
+            // reflection models, Ns runs from 0 (rough) to 1000 (mirror)
+            Some(3) | Some(5) | Some(8) => Arc::new(Metal {
+                albedo: self.ks.unwrap_or_else(Color::white),
+                fuzz: (1. - self.ns.unwrap_or(1000.) / 1000.).clamp(0., 1.),
+            }),
+            _ => Arc::new(Lambert {
+                albedo: self.kd.unwrap_or_else(|| Color::of_rgb(0.8, 0.8, 0.8)),
+            }),
+        }
+    }
+}
+
+pub fn parse_mtl(src: &str, file: &str) -> Result<HashMap<String, Arc<dyn Material>>> {
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args: Vec<&str> = parts.collect();
+
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                bail!("{}:{}: newmtl expects a material name", file, line_no);
+            }
+            entries.push((args[0].to_string(), MtlEntry::default()));
+            continue;
+        }
+
+        let entry = match entries.last_mut() {
+            Some((_, entry)) => entry,
+            None => bail!("{}:{}: `{}` before any newmtl", file, line_no, keyword),
+        };
+
+        let color = |args: &[&str]| -> Result<Color> {
+            let v = floats(args, 1, 3, file, line_no)?;
+            match v.len() {
+                // a single value means grey
+                1 => Ok(Color::of_rgb(v[0], v[0], v[0])),
+                3 => Ok(Color::of_rgb(v[0], v[1], v[2])),
+                _ => bail!("{}:{}: expected 1 or 3 color components", file, line_no),
+            }
+        };
+
+        match keyword {
+            "Kd" => entry.kd = Some(color(&args)?),
+            "Ks" => entry.ks = Some(color(&args)?),
+            "Ke" => entry.ke = Some(color(&args)?),
+            "Ni" => entry.ni = Some(floats(&args, 1, 1, file, line_no)?[0]),
+            "Ns" => entry.ns = Some(floats(&args, 1, 1, file, line_no)?[0]),
+            "illum" => {
+                let illum = args
+                    .first()
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| anyhow!("{}:{}: illum expects an integer", file, line_no))?;
+                entry.illum = Some(illum);
+            }
+            // ambient, transparency and texture maps aren't supported yet
+            _ => {}
+        }
+    }
+
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| (name, entry.into_material()))
+        .collect())
+}
+
+#[test]
+fn test_parse_obj() {
+    let src = "
+        # a unit quad and a triangle in their own group
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vt 0 0
+        vt 1 0
+        vt 1 1
+        vt 0 1
+        vn 0 0 1
+        f 1/1/1 2/2/1 3/3/1 4/4/1
+        g tri
+        v 0 0 1
+        f -1 -4 -3
+    ";
+
+    let groups = parse_obj(src, "test.obj", Path::new(".")).unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].name, "default");
+    assert_eq!(groups[0].mesh.len(), 2);
+    assert_eq!(groups[0].mesh.normals.len(), 4);
+    assert_eq!(groups[0].mesh.uvs.len(), 4);
+    assert_eq!(groups[1].name, "tri");
+    assert_eq!(groups[1].mesh.len(), 1);
+    assert_eq!(groups[1].mesh.vertices[0], vec3![0., 0., 1.]);
+
+    let err = parse_obj("v 0 0 0\nf 1 2 3\n", "bad.obj", Path::new("."))
+        .err()
+        .unwrap();
+    assert!(err.to_string().starts_with("bad.obj:2:"));
+}
@@ -0,0 +1,359 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::material::*;
+use crate::mesh::*;
+use crate::vec3::*;
+use crate::*;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, Scalar::F32 | Scalar::F64)
+    }
+}
+
+enum Property {
+    Scalar(Scalar, String),
+    // count type, item type, name
+    List(Scalar, Scalar, String),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(_, name) | Property::List(_, _, name) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    props: Vec<Property>,
+}
+
+// reads scalars out of the body in whichever encoding the header declared
+struct Body<'a> {
+    format: Format,
+    data: &'a [u8],
+    pos: usize,
+    file: &'a str,
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: Scalar) -> Result<f64> {
+        if self.format == Format::Ascii {
+            return self.read_ascii();
+        }
+
+        let size = ty.size();
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + size)
+            .ok_or_else(|| anyhow!("{}: unexpected end of file", self.file))?;
+        self.pos += size;
+
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(bytes);
+        // normalize to little endian so we only decode one way
+        if self.format == Format::BinaryBigEndian {
+            buf[..size].reverse();
+        }
+
+        Ok(match ty {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+
+    fn read_ascii(&mut self) -> Result<f64> {
+        while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            bail!("{}: unexpected end of file", self.file);
+        }
+
+        let token = String::from_utf8_lossy(&self.data[start..self.pos]);
+        token
+            .parse()
+            .map_err(|_| anyhow!("{}: invalid number `{}`", self.file, token))
+    }
+}
+
+pub fn load_ply(path: impl AsRef<Path>, mat: Arc<dyn Material>) -> Result<TriangleMesh> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    parse_ply(&data, &path.display().to_string(), mat)
+}
+
+fn parse_header(data: &[u8], file: &str) -> Result<(Format, Vec<Element>, usize)> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut pos = 0;
+
+    for line_no in 1.. {
+        let end = data[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| anyhow!("{}: missing end_header", file))?;
+        let line = std::str::from_utf8(&data[pos..pos + end])
+            .map_err(|_| anyhow!("{}:{}: header is not valid text", file, line_no))?
+            .trim();
+        pos += end + 1;
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["ply"] if line_no == 1 => {}
+            _ if line_no == 1 => bail!("{}:1: not a ply file", file),
+            ["format", f, _] => {
+                format = Some(match *f {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => bail!("{}:{}: unknown format `{}`", file, line_no, f),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["element", name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| anyhow!("{}:{}: invalid element count `{}`", file, line_no, count))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    props: Vec::new(),
+                });
+            }
+            ["property", "list", count_ty, item_ty, name] => {
+                let count_ty = Scalar::parse(count_ty)
+                    .ok_or_else(|| anyhow!("{}:{}: unknown type `{}`", file, line_no, count_ty))?;
+                let item_ty = Scalar::parse(item_ty)
+                    .ok_or_else(|| anyhow!("{}:{}: unknown type `{}`", file, line_no, item_ty))?;
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("{}:{}: property before any element", file, line_no))?;
+                element.props.push(Property::List(count_ty, item_ty, name.to_string()));
+            }
+            ["property", ty, name] => {
+                let ty = Scalar::parse(ty)
+                    .ok_or_else(|| anyhow!("{}:{}: unknown type `{}`", file, line_no, ty))?;
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("{}:{}: property before any element", file, line_no))?;
+                element.props.push(Property::Scalar(ty, name.to_string()));
+            }
+            ["end_header"] => break,
+            _ => bail!("{}:{}: malformed header line `{}`", file, line_no, line),
+        }
+    }
+
+    let format = format.ok_or_else(|| anyhow!("{}: missing format line", file))?;
+    Ok((format, elements, pos))
+}
+
+pub fn parse_ply(data: &[u8], file: &str, mat: Arc<dyn Material>) -> Result<TriangleMesh> {
+    let (format, elements, body_start) = parse_header(data, file)?;
+    let mut body = Body {
+        format,
+        data,
+        pos: body_start,
+        file,
+    };
+
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+
+    for element in &elements {
+        let find = |name: &str| element.props.iter().position(|p| p.name() == name);
+
+        let position = (find("x"), find("y"), find("z"));
+        let normal = (find("nx"), find("ny"), find("nz"));
+        let color = (find("red"), find("green"), find("blue"));
+        let face = find("vertex_indices").or_else(|| find("vertex_index"));
+
+        // 8 bit colors are stored 0-255, float ones 0-1
+        let color_scale = match color.0.map(|i| &element.props[i]) {
+            Some(Property::Scalar(ty, _)) if ty.is_float() => 1.,
+            _ => 1. / 255.,
+        };
+
+        let mut values = vec![0.; element.props.len()];
+        let mut list = Vec::new();
+
+        for n in 0..element.count {
+            for (i, prop) in element.props.iter().enumerate() {
+                match prop {
+                    Property::Scalar(ty, _) => values[i] = body.read(*ty)?,
+                    Property::List(count_ty, item_ty, _) => {
+                        let count = body.read(*count_ty)? as usize;
+                        let is_face = Some(i) == face;
+                        if is_face {
+                            list.clear();
+                        }
+                        for _ in 0..count {
+                            let v = body.read(*item_ty)?;
+                            if is_face {
+                                if v < 0. || v.fract() != 0. {
+                                    bail!("{}: face {} has invalid vertex index {}", file, n, v);
+                                }
+                                list.push(v as usize);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                if let (Some(x), Some(y), Some(z)) = position {
+                    vertices.push(vec3![values[x], values[y], values[z]]);
+                } else {
+                    bail!("{}: vertex element is missing x, y or z", file);
+                }
+                if let (Some(x), Some(y), Some(z)) = normal {
+                    let nv = vec3![values[x], values[y], values[z]];
+                    normals.push(if nv.is_zero() { nv } else { nv.unit_vec() });
+                }
+                if let (Some(r), Some(g), Some(b)) = color {
+                    colors.push(Color::of_rgb(values[r], values[g], values[b]).mult(color_scale));
+                }
+            } else if element.name == "face" && face.is_some() {
+                if list.len() < 3 {
+                    bail!("{}: face {} has fewer than 3 vertices", file, n);
+                }
+                if let Some(bad) = list.iter().find(|&&i| i >= vertices.len()) {
+                    bail!("{}: face {} references missing vertex {}", file, n, bad);
+                }
+                // fan triangulation around the first corner
+                for k in 1..list.len() - 1 {
+                    indices.push([list[0], list[k], list[k + 1]]);
+                }
+            }
+        }
+    }
+
+    Ok(TriangleMesh::new(vertices, normals, Vec::new(), indices, mat).with_colors(colors))
+}
+
+#[test]
+fn test_parse_ply() {
+    let mat: Arc<dyn Material> = Arc::new(VertexColor);
+
+    let ascii = "ply
+format ascii 1.0
+comment unit quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+";
+    let mesh = parse_ply(ascii.as_bytes(), "quad.ply", mat.clone()).unwrap();
+    assert_eq!(mesh.vertices.len(), 4);
+    assert_eq!(mesh.len(), 2);
+    assert_eq!(mesh.colors.len(), 4);
+    assert_eq!(mesh.colors[1].g, 1.);
+
+    // the same triangle encoded both ways round
+    for &(format, big) in &[("binary_little_endian", false), ("binary_big_endian", true)] {
+        let mut data = format!(
+            "ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+             property double z\nelement face 1\nproperty list uchar uint vertex_indices\nend_header\n",
+            format
+        )
+        .into_bytes();
+        for v in &[[0f64, 0., 0.], [1., 0., 0.], [0., 1., 2.]] {
+            for c in &v[..2] {
+                let f = *c as f32;
+                data.extend(&if big { f.to_be_bytes() } else { f.to_le_bytes() });
+            }
+            data.extend(&if big { v[2].to_be_bytes() } else { v[2].to_le_bytes() });
+        }
+        data.push(3);
+        for i in 0u32..3 {
+            data.extend(&if big { i.to_be_bytes() } else { i.to_le_bytes() });
+        }
+
+        let mesh = parse_ply(&data, "tri.ply", mat.clone()).unwrap();
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+        assert_eq!(mesh.vertices[2], vec3![0., 1., 2.]);
+    }
+
+    // negative and fractional indices are errors rather than vertex 0
+    for index in &["-1", "1.5"] {
+        let bad = format!(
+            "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+             element face 1\nproperty list uchar float vertex_indices\nend_header\n\
+             0 0 0\n1 0 0\n0 1 0\n3 0 {} 2\n",
+            index
+        );
+        assert!(parse_ply(bad.as_bytes(), "bad.ply", mat.clone()).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::volume::*;
+
+// ascii grids start with "nx ny nz" followed by nx * ny * nz densities,
+// x varying fastest, whitespace and line breaks are free form
+pub fn load_voxels_ascii(path: impl AsRef<Path>) -> Result<DensityGrid> {
+    let path = path.as_ref();
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    parse_voxels_ascii(&src, &path.display().to_string())
+}
+
+pub fn parse_voxels_ascii(src: &str, file: &str) -> Result<DensityGrid> {
+    let mut tokens = src
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or("");
+            line.split_whitespace().map(move |tok| (i + 1, tok))
+        });
+
+    let mut dim = || -> Result<usize> {
+        let (line_no, tok) = tokens
+            .next()
+            .ok_or_else(|| anyhow!("{}: missing grid dimensions", file))?;
+        match tok.parse() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => bail!("{}:{}: invalid grid dimension `{}`", file, line_no, tok),
+        }
+    };
+    let dims = (dim()?, dim()?, dim()?);
+
+    let data = tokens
+        .map(|(line_no, tok)| {
+            tok.parse::<f64>()
+                .map_err(|_| anyhow!("{}:{}: invalid density `{}`", file, line_no, tok))
+        })
+        .collect::<Result<Vec<f64>>>()?;
+
+    let expected = voxel_count(dims, file)?;
+    if data.len() != expected {
+        bail!("{}: expected {} densities, found {}", file, expected, data.len());
+    }
+
+    Ok(DensityGrid::new(dims, data))
+}
+
+// number of voxels in the grid, rejecting empty or overflowing dimensions
+fn voxel_count(dims: (usize, usize, usize), file: &str) -> Result<usize> {
+    if dims.0 == 0 || dims.1 == 0 || dims.2 == 0 {
+        bail!("{}: grid dimensions {}x{}x{} are empty", file, dims.0, dims.1, dims.2);
+    }
+    dims.0
+        .checked_mul(dims.1)
+        .and_then(|n| n.checked_mul(dims.2))
+        .ok_or_else(|| anyhow!("{}: grid dimensions are too large", file))
+}
+
+// raw grids are bare little endian f32 densities, x varying fastest
+pub fn load_voxels_raw(path: impl AsRef<Path>, dims: (usize, usize, usize)) -> Result<DensityGrid> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let expected = voxel_count(dims, &file)?
+        .checked_mul(4)
+        .ok_or_else(|| anyhow!("{}: grid dimensions are too large", file))?;
+    let bytes = std::fs::read(path).with_context(|| format!("failed to read {}", file))?;
+
+    if bytes.len() != expected {
+        bail!(
+            "{}: expected {} bytes for a {}x{}x{} grid, found {}",
+            file,
+            expected,
+            dims.0,
+            dims.1,
+            dims.2,
+            bytes.len()
+        );
+    }
+
+    let data = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+        .collect();
+
+    Ok(DensityGrid::new(dims, data))
+}
+
+#[test]
+fn test_raw_dims_are_validated() {
+    let path = std::env::temp_dir().join("test_raw_dims_are_validated.raw");
+    std::fs::write(&path, []).unwrap();
+
+    // both used to panic rather than fail
+    assert!(load_voxels_raw(&path, (0, 1, 1)).is_err());
+    assert!(load_voxels_raw(&path, (usize::MAX, 2, 1)).is_err());
+    assert!(parse_voxels_ascii(&format!("{} 2 1\n", usize::MAX), "big.vox").is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
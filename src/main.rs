@@ -7,15 +7,35 @@ use vec3::Point3;
 use std::{fs::File, rc::Rc, sync::Arc};
 use std::{io::Write, sync::atomic::AtomicI64};
 
-mod material;
-mod object;
+pub mod material;
+pub mod object;
 mod ray;
 
 #[macro_use]
-mod vec3;
+pub mod vec3;
 mod bounding_box;
-
-use crate::object::{Object, Sphere};
+pub mod bvh;
+pub mod instance;
+pub mod triangle;
+pub mod mesh;
+pub mod quad;
+pub mod cuboid;
+pub mod quadric;
+pub mod roots;
+pub mod torus;
+pub mod medium;
+pub mod volume;
+pub mod sdf;
+pub mod csg;
+pub mod plane;
+pub mod heightfield;
+pub mod subdivision;
+pub mod curve;
+pub mod loader;
+
+use crate::bvh::BuildStats;
+use crate::object::{Object, Sphere, T_MAX, T_MIN};
+use crate::plane::Plane;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 use rand::{
@@ -110,7 +130,7 @@ impl Image {
 }
 
 #[derive(Debug, Clone, Copy)]
-struct Camera {
+pub struct Camera {
     origin: Point3,
     look_dir: Vec3,
     up_dir: Vec3,
@@ -118,6 +138,9 @@ struct Camera {
     viewport_height: usize,
     fov: f64,
     focus_dist: f64,
+    // rays are cast at a random time between the shutter opening and closing
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -140,9 +163,17 @@ impl Camera {
             viewport_height,
             fov: fov.to_radians(),
             focus_dist: (look_point - origin).mag(),
+            shutter_open: 0.,
+            shutter_close: 0.,
         }
     }
 
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter_open = open;
+        self.shutter_close = close.max(open);
+        self
+    }
+
     pub fn cast_ray(&self, x: i32, y: i32) -> Ray {
         // distance in front of the camera of the projection plane
         //
@@ -174,14 +205,17 @@ impl Camera {
             + lense_radius * rand_in_circle.x() * cross_plane
             + lense_radius * rand_in_circle.y() * self.up_dir;
         let cast_dir = cast_point - ray_origin;
+        let time = Uniform::new_inclusive(self.shutter_open, self.shutter_close).sample(&mut rng);
 
-        Ray::new(ray_origin, cast_dir)
+        Ray::with_time(ray_origin, cast_dir, time)
     }
 }
 
 pub struct Scene {
     cam: Camera,
     objects: Vec<Box<Object>>,
+    // how the hierarchy over the objects turned out, if one was built
+    stats: Option<BuildStats>,
 }
 
 impl Scene {
@@ -195,18 +229,16 @@ impl Scene {
             point: Vec3::empty(),
             normal: Vec3::empty(),
             front_face: true,
+            bary: (0., 0.),
+            uv: (0., 0.),
             mat: Arc::new(Lambert {
                 albedo: Color::of_rgb(0.5, 0.5, 0.5),
             }),
         };
 
         let closest_hit = self.objects.iter().fold(infinity_hit, |acc, obj| {
-            if let Some(hit) = obj.hit(&ray) {
-                if hit.t < acc.t {
-                    return hit;
-                }
-            }
-            acc
+            // only hits in front of the closest one so far are of interest
+            obj.hit(&ray, T_MIN, acc.t.min(T_MAX)).unwrap_or(acc)
         });
 
         if closest_hit.t == f64::INFINITY {
@@ -283,10 +315,9 @@ pub fn plane_scene() -> Vec<Box<dyn Object>> {
     let focus_point = vec3!(5., 10., 4.);
     objects.push(mirror);
 
-    objects.push(Box::new(Sphere::new(
-        vec3!(0., 0., -100000.),
-        100000.,
-        Color::of_rgb(0.5, 0.5, 0.5),
+    objects.push(Box::new(Plane::new(
+        vec3!(0., 0., 0.),
+        vec3!(0., 0., 1.),
         Arc::new(
             Lambert {
             albedo: Color::of_rgb(0.5, 0.5, 0.5),
@@ -502,8 +533,10 @@ pub fn cornell_box() -> Arc<Scene> {
     */
 
 
-    let objects : Vec<Box<dyn Object>> = vec![Box::new(ObjectGroup::create_hierarchy(objects))];
-    let scene = Arc::from(Scene { cam, objects });
+    let group = ObjectGroup::create_hierarchy(objects);
+    let stats = Some(group.stats().clone());
+    let objects : Vec<Box<dyn Object>> = vec![Box::new(group)];
+    let scene = Arc::from(Scene { cam, objects, stats });
     scene
 }
 
@@ -511,6 +544,9 @@ fn main() {
 
     let mut img = Image::new(VIEWPORT_WIDTH, VIEWPORT_HEIGHT);
     let scene = cornell_box();
+    if let Some(stats) = &scene.stats {
+        eprintln!("{}", stats);
+    }
 
     let SAMPLES: i32 = 5000;
 
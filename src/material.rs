@@ -22,7 +22,23 @@ impl Material for Lambert {
             dir = hit.normal;
         }
 
-        Some((self.albedo, Ray::new(hit.point, dir)))
+        Some((self.albedo, Ray::with_time(hit.point, dir, ray.time)))
+    }
+}
+
+// lambertian that takes its albedo from the surface color of the hit,
+// e.g. interpolated mesh vertex colors
+pub struct VertexColor;
+
+impl Material for VertexColor {
+    fn scatter(&self, ray: &Ray, hit: &RayHit) -> Option<(Color, Ray)> {
+        let mut dir = Vec3::rand_in_hemisphere(&hit.normal);
+
+        if dir.is_zero() {
+            dir = hit.normal;
+        }
+
+        Some((hit.col, Ray::with_time(hit.point, dir, ray.time)))
     }
 }
 
@@ -35,9 +51,10 @@ impl Material for Metal {
     fn scatter(&self, ray: &Ray, hit: &RayHit) -> Option<(Color, Ray)> {
         let reflected = ray.dir.reflect(&hit.normal);
 
-        let scattered = Ray::new(
+        let scattered = Ray::with_time(
             hit.point,
             reflected + Vec3::rand_in_unit_circle() * self.fuzz,
+            ray.time,
         );
 
         (scattered.dir.dot(&hit.normal) >= 0. || true).then(|| (self.albedo, scattered))
@@ -84,7 +101,7 @@ impl Material for Glass {
             Vec3::refract(incident, &normal.unit_vec(), ref_indexes.0, ref_indexes.1)
         };
 
-        Some((Color::white(), Ray::new(hit.point, scattered)))
+        Some((Color::white(), Ray::with_time(hit.point, scattered, ray.time)))
     }
 }
 
@@ -101,3 +118,47 @@ impl Material for DiffuseLight {
         self.col
     }
 }
+
+// phase function scattering equally in every direction, for participating media
+pub struct Isotropic {
+    pub albedo: Color,
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: &Ray, hit: &RayHit) -> Option<(Color, Ray)> {
+        Some((self.albedo, Ray::with_time(hit.point, Vec3::rand_unit_vec(), ray.time)))
+    }
+}
+
+// Henyey-Greenstein phase function, g > 0 scatters forward, g < 0 backward
+// and g = 0 is isotropic
+pub struct HenyeyGreenstein {
+    pub albedo: Color,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    // cosine of the angle between the incoming and scattered directions
+    fn sample_cos_theta(&self, xi: f64) -> f64 {
+        let g = self.g;
+        if g.abs() < 1e-3 {
+            return 1. - 2. * xi;
+        }
+        let s = (1. - g * g) / (1. - g + 2. * g * xi);
+        ((1. + g * g - s * s) / (2. * g)).clamp(-1., 1.)
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray: &Ray, hit: &RayHit) -> Option<(Color, Ray)> {
+        let mut rng = rand::thread_rng();
+        let cos_theta = self.sample_cos_theta(rng.gen());
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * std::f64::consts::PI * rng.gen::<f64>();
+
+        let (a, b) = ray.dir.orthonormal_basis();
+        let dir = ray.dir * cos_theta + a * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin());
+
+        Some((self.albedo, Ray::with_time(hit.point, dir, ray.time)))
+    }
+}
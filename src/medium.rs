@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::vec3::*;
+use crate::*;
+
+// fog filling a closed boundary object, rays scatter after an exponentially
+// distributed distance inside it
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn Object>,
+    pub density: f64,
+    // usually Isotropic or HenyeyGreenstein
+    pub phase: Arc<dyn Material>,
+}
+
+unsafe impl Sync for ConstantMedium {}
+unsafe impl Send for ConstantMedium {}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Object>, density: f64, phase: Arc<dyn Material>) -> Self {
+        Self {
+            boundary,
+            density,
+            phase,
+        }
+    }
+}
+
+// distances along ray where it enters and leaves boundary, assumes the
+// boundary is convex. this looks at the whole ray, a caller's interval can
+// cut into the span but doesn't change where the boundary is
+pub fn boundary_span(boundary: &dyn Object, ray: &Ray) -> Option<(f64, f64)> {
+    let first = boundary.hit(ray, T_MIN, T_MAX)?;
+    if !first.front_face {
+        // the ray starts inside
+        return Some((0., first.t));
+    }
+
+    let inside = Ray::with_time(first.point, ray.dir, ray.time);
+    let exit = boundary.hit(&inside, T_MIN, T_MAX)?;
+    Some((first.t, first.t + exit.t))
+}
+
+pub fn medium_hit(ray: &Ray, t: f64, phase: &Arc<dyn Material>) -> RayHit {
+    RayHit {
+        col: Color::white(),
+        point: ray.cast(t),
+        t,
+        // arbitrary, phase functions don't look at it
+        normal: vec3![1., 0., 0.],
+        front_face: true,
+        bary: (0., 0.),
+        uv: (0., 0.),
+        mat: phase.clone(),
+    }
+}
+
+impl Object for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let (enter, exit) = boundary_span(self.boundary.as_ref(), ray)?;
+        // only the fog inside [t_min, t_max] can scatter
+        let start = enter.max(t_min);
+        let exit = exit.min(t_max);
+        if exit <= start {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+        // free flight distance, 1 - xi keeps us away from ln(0)
+        let dist = -(1. - rng.gen::<f64>()).ln() / self.density;
+        if dist > exit - start {
+            return None;
+        }
+
+        Some(medium_hit(ray, start + dist, &self.phase))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[test]
+fn test_constant_medium_density() {
+    let boundary: Arc<dyn Object> = Arc::new(Sphere::new(
+        vec3![0., 0., 0.],
+        1.,
+        Color::white(),
+        Arc::new(Lambert {
+            albedo: Color::white(),
+        }),
+    ));
+    let phase: Arc<dyn Material> = Arc::new(Isotropic {
+        albedo: Color::white(),
+    });
+    let ray = Ray::new(vec3![-5., 0., 0.], vec3![1., 0., 0.]);
+
+    let thick = ConstantMedium::new(boundary.clone(), 1e9, phase.clone());
+    let hit = thick.hit(&ray, T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 4.).abs() < 1e-6);
+
+    let thin = ConstantMedium::new(boundary.clone(), 1e-9, phase.clone());
+    assert!(thin.hit(&ray, T_MIN, T_MAX).is_none());
+
+    // starting inside the fog
+    let inside = Ray::new(vec3![0., 0., 0.], vec3![1., 0., 0.]);
+    assert!((thick.hit(&inside, T_MIN, T_MAX).unwrap().t - T_MIN).abs() < 1e-6);
+
+    // hits never land before the start of the interval
+    let hit = thick.hit(&ray, 4.5, T_MAX).unwrap();
+    assert!((hit.t - 4.5).abs() < 1e-6);
+    assert!(thick.hit(&ray, 6.5, T_MAX).is_none());
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::bvh::*;
+use crate::material::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::triangle::*;
+use crate::vec3::*;
+use crate::*;
+
+pub struct TriangleMesh {
+    pub vertices: Vec<Point3>,
+    // either empty or one normal per vertex
+    pub normals: Vec<Vec3>,
+    // either empty or one uv per vertex
+    pub uvs: Vec<(f64, f64)>,
+    // either empty or one color per vertex, interpolated into RayHit::col
+    pub colors: Vec<Color>,
+    pub indices: Vec<[usize; 3]>,
+    pub mat: Arc<dyn Material>,
+    // leaves cover contiguous ranges of indices
+    bvh: Bvh,
+}
+
+unsafe impl Sync for TriangleMesh {}
+unsafe impl Send for TriangleMesh {}
+
+impl TriangleMesh {
+    pub fn new(
+        vertices: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        assert!(normals.is_empty() || normals.len() == vertices.len());
+        assert!(uvs.is_empty() || uvs.len() == vertices.len());
+
+        // reorders the index list so every leaf owns a contiguous range of it
+        let bbs: Vec<AABB> = indices
+            .iter()
+            .map(|&[a, b, c]| AABB::from_points(&[vertices[a], vertices[b], vertices[c]]))
+            .collect();
+        let (bvh, order) = Bvh::build(&bbs);
+        let indices = order.iter().map(|&i| indices[i]).collect();
+
+        Self {
+            vertices,
+            normals,
+            uvs,
+            colors: Vec::new(),
+            indices,
+            mat,
+            bvh,
+        }
+    }
+
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Self {
+        assert!(colors.is_empty() || colors.len() == self.vertices.len());
+        self.colors = colors;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn stats(&self) -> &BuildStats {
+        &self.bvh.stats
+    }
+
+    fn hit_tri(&self, tri: usize, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let [a, b, c] = self.indices[tri];
+        let (v0, v1, v2) = (&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+        let (t, u, v) = intersect_triangle(ray, v0, v1, v2, t_min, t_max)?;
+
+        let normals = if self.normals.is_empty() {
+            None
+        } else {
+            Some([&self.normals[a], &self.normals[b], &self.normals[c]])
+        };
+
+        let uv = if self.uvs.is_empty() {
+            (u, v)
+        } else {
+            let w = 1. - u - v;
+            let (uv0, uv1, uv2) = (self.uvs[a], self.uvs[b], self.uvs[c]);
+            (
+                w * uv0.0 + u * uv1.0 + v * uv2.0,
+                w * uv0.1 + u * uv1.1 + v * uv2.1,
+            )
+        };
+
+        let mut hit = triangle_hit(ray, [v0, v1, v2], normals, t, (u, v), uv, &self.mat);
+        if !self.colors.is_empty() {
+            hit.col = self.colors[a]
+                .mult(1. - u - v)
+                .add(&self.colors[b].mult(u))
+                .add(&self.colors[c].mult(v));
+        }
+
+        Some(hit)
+    }
+}
+
+impl Object for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+        self.bvh.traverse(ray, t_min, t_max, |range, mut t_max| {
+            let mut found = None;
+            for tri in range {
+                if let Some(hit) = self.hit_tri(tri, ray, t_min, t_max) {
+                    t_max = hit.t;
+                    found = Some(t_max);
+                    closest = Some(hit);
+                }
+            }
+            found
+        });
+        closest
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.bvh.any(ray, t_min, t_max, |range| {
+            self.indices[range].iter().any(|&[a, b, c]| {
+                let (v0, v1, v2) = (&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+                intersect_triangle(ray, v0, v1, v2, t_min, t_max).is_some()
+            })
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bvh.bounding_box()
+    }
+}
+
+#[test]
+fn test_mesh_matches_triangles() {
+    // a bumpy 8x8 grid of quads, each split in two triangles
+    let n = 8;
+    let mut vertices = Vec::new();
+    for y in 0..=n {
+        for x in 0..=n {
+            let h = ((x * 7 + y * 3) % 5) as f64 * 0.1;
+            vertices.push(vec3![x as f64, y as f64, h]);
+        }
+    }
+
+    let mut indices = Vec::new();
+    for y in 0..n {
+        for x in 0..n {
+            let i = y * (n + 1) + x;
+            indices.push([i, i + 1, i + n + 1]);
+            indices.push([i + 1, i + n + 2, i + n + 1]);
+        }
+    }
+
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    let tris: Vec<Triangle> = indices
+        .iter()
+        .map(|&[a, b, c]| Triangle::new(vertices[a], vertices[b], vertices[c], mat.clone()))
+        .collect();
+    let mesh = TriangleMesh::new(vertices, Vec::new(), Vec::new(), indices, mat);
+
+    for i in 0..50 {
+        let origin = vec3![i as f64 * 0.17, 8. - i as f64 * 0.13, 3.];
+        let ray = Ray::new(origin, vec3![0.1, 0.05, -1.]);
+
+        let expected = tris
+            .iter()
+            .filter_map(|tri| tri.hit(&ray, T_MIN, T_MAX))
+            .map(|hit| hit.t)
+            .fold(f64::INFINITY, f64::min);
+        let got = mesh.hit(&ray, T_MIN, T_MAX).map_or(f64::INFINITY, |hit| hit.t);
+        assert_eq!(expected, got);
+
+        if got.is_finite() {
+            assert!(mesh.occluded(&ray, T_MIN, 1.001 * got));
+            assert!(!mesh.occluded(&ray, T_MIN, 0.999 * got));
+        } else {
+            assert!(!mesh.occluded(&ray, T_MIN, T_MAX));
+        }
+    }
+}
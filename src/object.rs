@@ -5,6 +5,7 @@ use crate::material::*;
 use crate::ray::*;
 use crate::vec3::*;
 use crate::bounding_box::*;
+use crate::bvh::*;
 use crate::*;
 
 pub struct RayHit {
@@ -13,93 +14,136 @@ pub struct RayHit {
     pub t: f64,
     pub normal: Vec3,
     pub front_face: bool,
+    // barycentric (u, v) of the hit for triangles, w = 1 - u - v
+    pub bary: (f64, f64),
+    // surface coordinates, used for texture lookups
+    pub uv: (f64, f64),
     pub mat: Arc<dyn Material>,
 }
 
 pub trait Object: Sync + Send {
-    fn hit(&self, ray: &Ray) -> Option<RayHit>;
+    // closest hit with t in [t_min, t_max]
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit>;
     fn bounding_box(&self) -> Option<AABB>;
+
+    // whether anything is hit in [t_min, t_max], for shadow rays that don't care
+    // which hit is closest. worth overriding whenever building the RayHit
+    // or finding the closest one costs extra
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit(ray, t_min, t_max).is_some()
+    }
 }
 
 pub struct ObjectGroup {
+    // ordered so each leaf of the bvh covers a contiguous range
     objs : Vec<Box<dyn Object>>,
-    bb : AABB,
+    // index of each object in the vec the group was created from
+    ids : Vec<usize>,
+    bvh : Bvh,
+    // objects without a bounding box, like planes, are tested on every ray
+    unbounded : Vec<Box<dyn Object>>,
+    unbounded_ids : Vec<usize>,
 }
 
 impl ObjectGroup {
-    fn new(objs: Vec<Box<dyn Object>>) -> Self {
-        let bb = objs.iter().map(|x| x.bounding_box())
-                        .reduce(|a , b| {
-                            let a = a?;
-                            b.map(|b| {
-                                a.combine(&b)
-                            })
-                        }).unwrap().unwrap();
-
-
-        Self { objs, bb }
+    pub fn create_hierarchy(objs : Vec<Box<dyn Object>>) -> Self {
+        // unbounded objects can't be placed in the tree, so they stay at the root
+        // bounds are computed once up front, some objects are slow to bound
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = objs.into_iter()
+                        .enumerate()
+                        .map(|(id, x)| (id, x.bounding_box(), x))
+                        .partition(|(_, bb, _)| bb.is_some());
+        let (unbounded_ids, unbounded) = unbounded.into_iter()
+                        .map(|(id, _, x)| (id, x))
+                        .unzip();
+
+        let bbs: Vec<AABB> = bounded.iter().map(|(_, bb, _)| bb.unwrap()).collect();
+        let (bvh, order) = Bvh::build(&bbs);
+
+        let mut slots: Vec<Option<_>> = bounded.into_iter().map(|(id, _, x)| Some((id, x))).collect();
+        let (ids, objs) = order.iter().map(|&i| slots[i].take().unwrap()).unzip();
+
+        Self { objs, ids, bvh, unbounded, unbounded_ids }
     }
 
-    pub fn create_hierarchy(mut objs : Vec<Box<dyn Object>>) -> Self {
-        let mut rng = rand::thread_rng();
-        if objs.len() <= 2 {
-            return Self::new(objs);
+    // lets f move or swap out objects, identified by their index in the vec
+    // the group was created from, then brings the tree up to date. it is
+    // refitted in place unless that leaves it degraded or an object lost or
+    // gained bounds, then it is rebuilt. returns whether it was rebuilt
+    pub fn update(&mut self, mut f : impl FnMut(usize, &mut Box<dyn Object>)) -> bool {
+        let bounded = self.ids.iter().zip(self.objs.iter_mut());
+        let unbounded = self.unbounded_ids.iter().zip(self.unbounded.iter_mut());
+        for (&id, obj) in bounded.chain(unbounded) {
+            f(id, obj);
         }
 
-        objs.sort_by_cached_key(|x| {
-            (x.bounding_box().unwrap().start[rng.gen_range(0..3)] * 100000.) as i64
-        });
-
-        let mut lhs = Vec::with_capacity(objs.len() / 2);
-        let mut rhs = Vec::with_capacity(objs.len() / 2);
-
-        let N = objs.len();
-        for (i, obj) in objs.into_iter().enumerate() {
-            if i < N / 2 {
-                lhs.push(obj)
-            } else {
-                rhs.push(obj)
+        // objects that gained a box belong in the tree rather than being
+        // tested on every ray
+        let gained = self.unbounded.iter().any(|x| x.bounding_box().is_some());
+        let bbs: Option<Vec<AABB>> = self.objs.iter().map(|x| x.bounding_box()).collect();
+        if let (false, Some(bbs)) = (gained, bbs) {
+            self.bvh.refit(&bbs);
+            if !self.bvh.degraded() {
+                return false;
             }
         }
 
-        let lhs = Self::create_hierarchy(lhs);
-        let rhs = Self::create_hierarchy(rhs);
-
-
-        return Self::new(vec![Box::new(lhs), Box::new(rhs)]);
+        // put everything back in creation order so ids stay the same
+        let ids = std::mem::take(&mut self.ids).into_iter()
+                        .chain(std::mem::take(&mut self.unbounded_ids));
+        let objs = std::mem::take(&mut self.objs).into_iter()
+                        .chain(std::mem::take(&mut self.unbounded));
+        let mut slots: Vec<Option<Box<dyn Object>>> = ids.clone().map(|_| None).collect();
+        for (id, obj) in ids.zip(objs) {
+            slots[id] = Some(obj);
+        }
+        *self = Self::create_hierarchy(slots.into_iter().map(Option::unwrap).collect());
+        true
     }
 
-
+    pub fn stats(&self) -> &BuildStats {
+        &self.bvh.stats
+    }
 }
 
 impl Object for ObjectGroup {
-    fn hit(&self, ray: &Ray) -> Option<RayHit> {
-        if !self.bb.hit(ray, T_MIN, T_MAX) {
-            return None;
+    fn hit(&self, ray: &Ray, t_min: f64, mut t_max: f64) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+        for obj in self.unbounded.iter() {
+            if let Some(hit) = obj.hit(ray, t_min, t_max) {
+                t_max = hit.t;
+                closest = Some(hit);
+            }
         }
 
-
-        self.objs.iter().fold(None, |acc, obj|{
-                if let Some(hit) = obj.hit(&ray) {
-                    match &acc {
-                        Some(acc) => {
-                            if hit.t < acc.t {
-                                return Some(hit)
-                            }
-                        }
-                        None => {
-                            return Some(hit)
-                        }
-                    }
+        self.bvh.traverse(ray, t_min, t_max, |range, mut t_max| {
+            let mut found = None;
+            for obj in self.objs[range].iter() {
+                if let Some(hit) = obj.hit(ray, t_min, t_max) {
+                    t_max = hit.t;
+                    found = Some(t_max);
+                    closest = Some(hit);
                 }
-                acc
             }
-        )
+            found
+        });
+
+        closest
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.unbounded.iter().any(|obj| obj.occluded(ray, t_min, t_max))
+            || self.bvh.any(ray, t_min, t_max, |range| {
+                self.objs[range].iter().any(|obj| obj.occluded(ray, t_min, t_max))
+            })
     }
 
 
     fn bounding_box(&self) -> Option<AABB> {
-        Some(self.bb)
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.bvh.bounding_box()
     }
 }
 
@@ -123,14 +167,9 @@ impl Sphere {
             mat,
         }
     }
-}
 
-const T_MIN: f64 = 0.0001;
-const T_MAX: f64 = 100000000.;
-
-impl Object for Sphere {
-    fn hit(&self, ray: &Ray) -> Option<RayHit> {
-        if !self.bounding_box().unwrap().hit(ray, T_MIN, T_MAX) {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        if !self.bounding_box().unwrap().hit(ray, t_min, t_max) {
             return None;
         }
 
@@ -155,15 +194,29 @@ impl Object for Sphere {
         let t1 = (-b - delta_sqrt) / (2.0);
         let t2 = (-b + delta_sqrt) / (2.0);
 
-        let valid_t = |t: f64| -> bool { T_MIN <= t && t <= T_MAX };
+        let valid_t = |t: f64| -> bool { t_min <= t && t <= t_max };
 
-        let t = if valid_t(t1) {
-            t1
+        if valid_t(t1) {
+            Some(t1)
         } else if valid_t(t2) {
-            t2
+            Some(t2)
         } else {
-            return None;
-        };
+            None
+        }
+    }
+}
+
+pub const T_MIN: f64 = 0.0001;
+pub const T_MAX: f64 = 100000000.;
+
+impl Object for Sphere {
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(ray, t_min, t_max).is_some()
+    }
+
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let t = self.intersect(ray, t_min, t_max)?;
+        let dir = &ray.dir;
 
         let intersection_point = ray.cast(t);
 
@@ -186,6 +239,8 @@ impl Object for Sphere {
             t,
             normal,
             front_face,
+            bary: (0., 0.),
+            uv: (0., 0.),
             mat:self.mat.clone()
         })
     }
@@ -199,6 +254,53 @@ impl Object for Sphere {
     }
 }
 
+// sphere whose center moves linearly from center0 at time0 to center1 at time1
+pub struct MovingSphere {
+    pub sphere : Sphere,
+    pub center1 : Point3,
+    pub time0 : f64,
+    pub time1 : f64,
+}
+
+impl MovingSphere {
+    pub fn new(center0: Point3, center1: Point3, time0: f64, time1: f64, r: f64, color: Color, mat: Arc<dyn Material>) -> Self {
+        Self {
+            sphere: Sphere::new(center0, r, color, mat),
+            center1,
+            time0,
+            time1,
+        }
+    }
+
+    fn offset_at(&self, time: f64) -> Vec3 {
+        (self.center1 - self.sphere.center) * shutter_fraction(time, self.time0, self.time1)
+    }
+}
+
+impl Object for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        // move the ray instead of the sphere
+        let offset = self.offset_at(ray.time);
+        let shifted = Ray::with_time(ray.origin - offset, ray.dir, ray.time);
+
+        let mut hit = self.sphere.hit(&shifted, t_min, t_max)?;
+        hit.point = hit.point + offset;
+        Some(hit)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let offset = self.offset_at(ray.time);
+        let shifted = Ray::with_time(ray.origin - offset, ray.dir, ray.time);
+        self.sphere.occluded(&shifted, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let bb = self.sphere.bounding_box()?;
+        let offset = self.center1 - self.sphere.center;
+        Some(bb.combine(&AABB::new(bb.start + offset, bb.end + offset)))
+    }
+}
+
 pub enum Axis {
     XY,
     XZ,
@@ -210,8 +312,8 @@ pub struct FlipFace {
 }
 
 impl Object for FlipFace {
-    fn hit(&self, ray: &Ray) -> Option<RayHit> {
-        let hit = self.obj.hit(ray)?;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let hit = self.obj.hit(ray, t_min, t_max)?;
         if hit.front_face {
             return Some(hit);
         }
@@ -223,6 +325,122 @@ impl Object for FlipFace {
     }
 }
 
+// places obj in the world with an affine transform, so one object can be
+// moved, rotated or instanced several times
+pub struct Transformed {
+    pub obj : Arc<dyn Object>,
+    pub xform : Mat4,
+    inv : Mat4,
+}
+
+impl Transformed {
+    pub fn new(obj: Arc<dyn Object>, xform: Mat4) -> Self {
+        let inv = xform.inverse().expect("transform must be invertible");
+        Self { obj, xform, inv }
+    }
+}
+
+// box around the eight transformed corners
+pub fn transform_bb(bb: &AABB, xform: &Mat4) -> AABB {
+    let mut corners = Vec::with_capacity(8);
+    for i in 0..8 {
+        let corner = vec3![
+            if i & 1 == 0 { bb.start.x() } else { bb.end.x() },
+            if i & 2 == 0 { bb.start.y() } else { bb.end.y() },
+            if i & 4 == 0 { bb.start.z() } else { bb.end.z() }
+        ];
+        corners.push(xform.transform_point(&corner));
+    }
+    AABB::from_points(&corners)
+}
+
+// the ray in object space, and how much longer distances are along it
+pub fn local_ray(inv: &Mat4, ray: &Ray) -> (Ray, f64) {
+    let dir = inv.transform_vector(&ray.dir);
+    // object space distances are scaled by the length of dir
+    let scale = dir.mag();
+    (Ray::with_time(inv.transform_point(&ray.origin), dir, ray.time), scale)
+}
+
+// hits obj through the inverse of its object to world transform
+pub fn hit_transformed(obj: &dyn Object, inv: &Mat4, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+    let (local, scale) = local_ray(inv, ray);
+
+    let mut hit = obj.hit(&local, t_min * scale, t_max * scale)?;
+    hit.t /= scale;
+    hit.point = ray.cast(hit.t);
+    // normals transform by the inverse transpose, which keeps front_face valid
+    hit.normal = inv.transpose().transform_vector(&hit.normal).unit_vec();
+    Some(hit)
+}
+
+impl Object for Transformed {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        hit_transformed(self.obj.as_ref(), &self.inv, ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let (local, scale) = local_ray(&self.inv, ray);
+        self.obj.occluded(&local, t_min * scale, t_max * scale)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.obj.bounding_box().map(|bb| transform_bb(&bb, &self.xform))
+    }
+}
+
+// fraction of the way from time0 to time1, clamped to the interval
+fn shutter_fraction(time: f64, time0: f64, time1: f64) -> f64 {
+    if time1 <= time0 {
+        return 0.;
+    }
+    ((time - time0) / (time1 - time0)).clamp(0., 1.)
+}
+
+// Transformed whose transform blends from xform0 at time0 to xform1 at time1,
+// good for motion blur where the change over the shutter is small
+pub struct MovingTransformed {
+    pub obj : Arc<dyn Object>,
+    pub xform0 : Mat4,
+    pub xform1 : Mat4,
+    pub time0 : f64,
+    pub time1 : f64,
+}
+
+impl MovingTransformed {
+    pub fn new(obj: Arc<dyn Object>, xform0: Mat4, xform1: Mat4, time0: f64, time1: f64) -> Self {
+        Self { obj, xform0, xform1, time0, time1 }
+    }
+
+    pub fn xform_at(&self, time: f64) -> Mat4 {
+        self.xform0.lerp(&self.xform1, shutter_fraction(time, self.time0, self.time1))
+    }
+}
+
+impl Object for MovingTransformed {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let inv = self.xform_at(ray.time).inverse()?;
+        hit_transformed(self.obj.as_ref(), &inv, ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        match self.xform_at(ray.time).inverse() {
+            Some(inv) => {
+                let (local, scale) = local_ray(&inv, ray);
+                self.obj.occluded(&local, t_min * scale, t_max * scale)
+            }
+            None => false,
+        }
+    }
+
+    // points move in straight lines under the blend, so the boxes at both
+    // ends cover every time in between
+    fn bounding_box(&self) -> Option<AABB> {
+        let bb = self.obj.bounding_box()?;
+        Some(transform_bb(&bb, &self.xform0).combine(&transform_bb(&bb, &self.xform1)))
+    }
+}
+
 pub struct Rect {
     pub p0 : (f64, f64),
     pub p1 : (f64, f64),
@@ -243,6 +461,25 @@ impl Rect {
         Self { p0, p1, k, axis, mat, perp, a0, a1 }
     }
 
+    // t where the ray crosses the rectangle
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let (perp, a0, a1) = (self.perp, self.a0, self.a1);
+        // t at intersection of the plane
+        let t = (self.k - ray.origin[perp]) / ray.dir[perp];
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+
+        let hit_0 = t.mul_add(ray.dir[a0], ray.origin[a0]);
+        let hit_1 = t.mul_add(ray.dir[a1], ray.origin[a1]);
+
+        if hit_0 < self.p0.0 || hit_0 > self.p0.1 || hit_1 < self.p1.0  || hit_1 > self.p1.1 {
+            return None;
+        }
+        Some(t)
+    }
+
     fn axis(axis : &Axis) -> (usize, usize, usize) {
         match axis {
             Axis::XY => {
@@ -266,21 +503,9 @@ unsafe impl Sync for Rect {}
 
 impl Object for Rect {
 
-    fn hit(&self, ray: &Ray) -> Option<RayHit> {
-        let (perp, a0, a1) = (self.perp, self.a0, self.a1);
-        // t at intersection of the plane
-        let t = (self.k - ray.origin[perp]) / ray.dir[perp];
-        if t < T_MIN || t > T_MAX {
-            return None;
-        }
-
-
-        let hit_0 = t.mul_add(ray.dir[a0], ray.origin[a0]);
-        let hit_1 = t.mul_add(ray.dir[a1], ray.origin[a1]);
-
-        if hit_0 < self.p0.0 || hit_0 > self.p0.1 || hit_1 < self.p1.0  || hit_1 > self.p1.1 {
-            return None;
-        }
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let t = self.intersect(ray, t_min, t_max)?;
+        let perp = self.perp;
 
         let mut normal = Vec3::empty();
         normal[perp] = 1.;
@@ -293,7 +518,11 @@ impl Object for Rect {
 
         let point = ray.cast(t);
 
-        Some(RayHit { col: Color::of_rgb(1.,0.,0.), point, t, normal, front_face, mat: self.mat.clone()})
+        Some(RayHit { col: Color::of_rgb(1.,0.,0.), point, t, normal, front_face, bary: (0., 0.), uv: (0., 0.), mat: self.mat.clone()})
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(ray, t_min, t_max).is_some()
     }
 
     fn bounding_box(&self) -> Option<AABB> {
@@ -311,3 +540,64 @@ impl Object for Rect {
         Some(AABB::new(small, big))
     }
 }
+
+#[test]
+fn test_motion() {
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    let down = vec3![0., 0., -1.];
+
+    let sphere = MovingSphere::new(vec3![0., 0., 0.], vec3![4., 0., 0.], 0., 1., 1., Color::white(), mat.clone());
+    assert!(sphere.hit(&Ray::with_time(vec3![0., 0., 5.], down, 0.), T_MIN, T_MAX).is_some());
+    assert!(sphere.hit(&Ray::with_time(vec3![0., 0., 5.], down, 1.), T_MIN, T_MAX).is_none());
+    let hit = sphere.hit(&Ray::with_time(vec3![2., 0., 5.], down, 0.5), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 4.).abs() < 1e-9);
+    assert!((hit.point - vec3![2., 0., 1.]).is_zero());
+    assert!(sphere.bounding_box().unwrap().end.x() >= 5.);
+
+    let ball: Arc<dyn Object> = Arc::new(Sphere::new(vec3![0., 0., 0.], 1., Color::white(), mat));
+
+    let moving = MovingTransformed::new(
+        ball,
+        Mat4::identity(),
+        Mat4::translate(&vec3![0., 4., 0.]),
+        0.,
+        1.,
+    );
+    let hit = moving.hit(&Ray::with_time(vec3![0., 3., 5.], down, 0.75), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 4.).abs() < 1e-9);
+    assert!(moving.hit(&Ray::with_time(vec3![0., 3., 5.], down, 0.), T_MIN, T_MAX).is_none());
+    assert!((moving.bounding_box().unwrap().end.y() - 5.).abs() < 1e-3);
+}
+
+#[test]
+fn test_transformed_interval() {
+    let unit_ball = || {
+        Sphere::new(
+            vec3![0., 0., 0.],
+            1.,
+            Color::white(),
+            Arc::new(Lambert {
+                albedo: Color::white(),
+            }),
+        )
+    };
+    let ball: Arc<dyn Object> = Arc::new(unit_ball());
+    let blas = Arc::new(ObjectGroup::create_hierarchy(vec![Box::new(unit_ball())]));
+    let shrink = Mat4::scale(&Vec3::of_scalar(1e-3));
+
+    // shadow rays see the same interval as hit once it is scaled into
+    // object space. the near side sits at half of T_MIN in world space
+    let grazing = Ray::new(vec3![-1e-3 - 0.5 * T_MIN, 0., 0.], vec3![1., 0., 0.]);
+    let objects: [&dyn Object; 3] = [
+        &Transformed::new(ball.clone(), shrink),
+        &MovingTransformed::new(ball, shrink, shrink, 0., 1.),
+        &crate::instance::Instance::new(blas, shrink),
+    ];
+    for tiny in objects {
+        assert!(tiny.hit(&grazing, T_MIN, 1e-3).is_none());
+        assert!(!tiny.occluded(&grazing, T_MIN, 1e-3));
+        assert!(tiny.occluded(&grazing, T_MIN, T_MAX));
+    }
+}
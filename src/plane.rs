@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::vec3::*;
+use crate::*;
+
+// infinite plane through point, facing along normal. it has no bounding box,
+// so hierarchies keep it outside the tree and test it on every ray
+pub struct Plane {
+    pub point: Point3,
+    pub mat: Arc<dyn Material>,
+
+    normal: Vec3,
+    // in-plane axes used for the uv coordinates
+    u: Vec3,
+    v: Vec3,
+}
+
+unsafe impl Sync for Plane {}
+unsafe impl Send for Plane {}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, mat: Arc<dyn Material>) -> Self {
+        let normal = normal.unit_vec();
+        let (u, v) = normal.orthonormal_basis();
+        Self {
+            point,
+            mat,
+            normal,
+            u,
+            v,
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let denom = self.normal.dot(&ray.dir);
+        // ray is parallel to the plane
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+
+        // measured from the plane point rather than the world origin, so
+        // precision doesn't depend on how far away the plane is defined
+        let t = (self.point - ray.origin).dot(&self.normal) / denom;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+        Some(t)
+    }
+}
+
+impl Object for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let t = self.intersect(ray, t_min, t_max)?;
+        let point = ray.cast(t);
+        let planar = point - self.point;
+
+        let (normal, front_face) = if self.normal.dot(&ray.dir) > 0. {
+            // we are below the plane
+            (-self.normal, false)
+        } else {
+            (self.normal, true)
+        };
+
+        Some(RayHit {
+            col: Color::white(),
+            point,
+            t,
+            normal,
+            front_face,
+            bary: (0., 0.),
+            uv: (planar.dot(&self.u), planar.dot(&self.v)),
+            mat: self.mat.clone(),
+        })
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(ray, t_min, t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        None
+    }
+}
+
+#[test]
+fn test_plane_in_hierarchy() {
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+
+    let mut objects: Vec<Box<dyn Object>> = Vec::new();
+    objects.push(Box::new(Plane::new(
+        vec3![0., 0., 0.],
+        vec3![0., 0., 2.],
+        mat.clone(),
+    )));
+    for i in 0..5 {
+        objects.push(Box::new(Sphere::new(
+            vec3![3. * i as f64, 0., 1.],
+            1.,
+            Color::white(),
+            mat.clone(),
+        )));
+    }
+
+    let group = ObjectGroup::create_hierarchy(objects);
+    assert!(group.bounding_box().is_none());
+
+    // far from every sphere, only the plane is hit
+    let hit = group
+        .hit(&Ray::new(vec3![1000., 500., 10.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 10.).abs() < 1e-9);
+    assert!(hit.front_face);
+    assert!((hit.normal - vec3![0., 0., 1.]).is_zero());
+
+    // the sphere in front of the plane wins
+    let hit = group
+        .hit(&Ray::new(vec3![6., 0., 10.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 8.).abs() < 1e-9);
+
+    // from below the plane faces away
+    let hit = group
+        .hit(&Ray::new(vec3![1000., 500., -10.], vec3![0., 0., 1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!(!hit.front_face);
+    assert!((hit.normal - vec3![0., 0., -1.]).is_zero());
+
+    // parallel rays never reach it
+    assert!(group
+        .hit(&Ray::new(vec3![1000., 500., 10.], vec3![1., 0., 0.]), T_MIN, T_MAX)
+        .is_none());
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::vec3::*;
+use crate::*;
+
+// parallelogram spanning corner, corner + u, corner + v and corner + u + v,
+// the normal follows the right hand rule from u to v
+pub struct Quad {
+    pub corner: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub mat: Arc<dyn Material>,
+
+    normal: Vec3,
+    d: f64,
+    // scaled normal, used to project hits back onto u and v
+    w: Vec3,
+}
+
+unsafe impl Sync for Quad {}
+unsafe impl Send for Quad {}
+
+impl Quad {
+    pub fn new(corner: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vec();
+        let d = normal.dot(&corner);
+        let w = n / n.mag_squared();
+
+        Self {
+            corner,
+            u,
+            v,
+            mat,
+            normal,
+            d,
+            w,
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    // (t, alpha, beta) where the ray crosses the quad
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = self.normal.dot(&ray.dir);
+        // ray is parallel to the plane
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+
+        let planar = ray.cast(t) - self.corner;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+        Some((t, alpha, beta))
+    }
+
+    pub fn corners(&self) -> [Point3; 4] {
+        [
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ]
+    }
+}
+
+impl Object for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let (t, alpha, beta) = self.intersect(ray, t_min, t_max)?;
+        let point = ray.cast(t);
+
+        let (normal, front_face) = if self.normal.dot(&ray.dir) > 0. {
+            // we are behind the quad
+            (-self.normal, false)
+        } else {
+            (self.normal, true)
+        };
+
+        Some(RayHit {
+            col: Color::white(),
+            point,
+            t,
+            normal,
+            front_face,
+            bary: (0., 0.),
+            uv: (alpha, beta),
+            mat: self.mat.clone(),
+        })
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(ray, t_min, t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::from_points(&self.corners()))
+    }
+}
+
+#[test]
+fn test_quad_hit() {
+    // tilted quad facing roughly +z
+    let quad = Quad::new(
+        vec3![0., 0., 0.],
+        vec3![2., 0., 0.],
+        vec3![0., 1., 1.],
+        Arc::new(Lambert {
+            albedo: Color::white(),
+        }),
+    );
+
+    let hit = quad
+        .hit(&Ray::new(vec3![0.5, 0.5, 5.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 4.5).abs() < 1e-9);
+    assert!((hit.uv.0 - 0.25).abs() < 1e-9);
+    assert!((hit.uv.1 - 0.5).abs() < 1e-9);
+    assert!(hit.front_face);
+    assert!((hit.normal - vec3![0., -1., 1.].unit_vec()).is_zero());
+
+    assert!(quad
+        .hit(&Ray::new(vec3![2.5, 0.5, 5.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .is_none());
+}
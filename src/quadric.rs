@@ -0,0 +1,426 @@
+use std::f64::consts::PI;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::roots::*;
+use crate::vec3::*;
+use crate::*;
+
+// The quadrics here stand on their base center and point up +z, sweeping
+// counter clockwise from +x up to phi_max. Wrap them in Transformed to orient
+// them any other way.
+
+fn phi_of(x: f64, y: f64) -> f64 {
+    let phi = y.atan2(x);
+    if phi < 0. {
+        phi + 2. * PI
+    } else {
+        phi
+    }
+}
+
+// points bounding an arc of the given radius from 0 to phi_max
+fn arc_points(radius: f64, phi_max: f64) -> Vec<(f64, f64)> {
+    let mut points = vec![(radius, 0.), (radius * phi_max.cos(), radius * phi_max.sin())];
+    for k in 1..4 {
+        let phi = k as f64 * PI / 2.;
+        if phi < phi_max {
+            points.push((radius * phi.cos(), radius * phi.sin()));
+        }
+    }
+    points
+}
+
+fn swept_bb(center: &Point3, radii: &[f64], z0: f64, z1: f64, phi_max: f64) -> AABB {
+    let mut points = Vec::new();
+    for &r in radii {
+        for (x, y) in arc_points(r, phi_max) {
+            points.push(*center + vec3![x, y, z0]);
+            points.push(*center + vec3![x, y, z1]);
+        }
+    }
+    AABB::from_points(&points)
+}
+
+// same orientation rules as Sphere, normals face the ray and front_face is
+// false when we hit from inside
+pub fn oriented_hit(ray: &Ray, t: f64, outward: Vec3, uv: (f64, f64), mat: &Arc<dyn Material>) -> RayHit {
+    let (normal, front_face) = if outward.dot(&ray.dir) > 0. {
+        (-outward, false)
+    } else {
+        (outward, true)
+    };
+
+    RayHit {
+        col: Color::white(),
+        point: ray.cast(t),
+        t,
+        normal,
+        front_face,
+        bary: (0., 0.),
+        uv,
+        mat: mat.clone(),
+    }
+}
+
+fn closest(a: Option<RayHit>, b: Option<RayHit>) -> Option<RayHit> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a.t <= b.t { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+// hit against a disk lying in z = h of the local frame, o is the ray origin
+// relative to the center and span the accepted range of t
+fn hit_cap(ray: &Ray, o: &Vec3, h: f64, radius: f64, inner_radius: f64, phi_max: f64, span: RangeInclusive<f64>) -> Option<(f64, f64, f64)> {
+    if ray.dir.z().abs() < 1e-12 {
+        return None;
+    }
+    let t = (h - o.z()) / ray.dir.z();
+    if !span.contains(&t) {
+        return None;
+    }
+
+    let (x, y) = (o.x() + t * ray.dir.x(), o.y() + t * ray.dir.y());
+    let dist_squared = x * x + y * y;
+    if dist_squared > radius * radius || dist_squared < inner_radius * inner_radius {
+        return None;
+    }
+
+    let phi = phi_of(x, y);
+    if phi > phi_max {
+        return None;
+    }
+    Some((t, phi, dist_squared.sqrt()))
+}
+
+pub struct Cylinder {
+    pub center: Point3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub phi_max: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+unsafe impl Sync for Cylinder {}
+unsafe impl Send for Cylinder {}
+
+impl Cylinder {
+    pub fn new(center: Point3, radius: f64, height: f64, capped: bool, mat: Arc<dyn Material>) -> Self {
+        Self {
+            center,
+            radius,
+            height,
+            capped,
+            phi_max: 2. * PI,
+            mat,
+        }
+    }
+
+    // only sweep the first `degrees` of the circle
+    pub fn with_phi_max(mut self, degrees: f64) -> Self {
+        self.phi_max = degrees.clamp(0., 360.).to_radians();
+        self
+    }
+}
+
+impl Cylinder {
+    // (t, point relative to center, phi) of the first hit on the curved wall
+    fn intersect_side(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, Vec3, f64)> {
+        let o = ray.origin - self.center;
+        let d = ray.dir;
+
+        let a = d.x() * d.x() + d.y() * d.y();
+        let b = 2. * (o.x() * d.x() + o.y() * d.y());
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+
+        // a ray running along the axis never touches the side
+        if a < 1e-12 {
+            return None;
+        }
+        let (t0, t1) = solve_quadratic(a, b, c)?;
+        [t0, t1].iter().copied().find_map(|t| {
+            if !(t_min..=t_max).contains(&t) {
+                return None;
+            }
+            let p = o + d * t;
+            let phi = phi_of(p.x(), p.y());
+            if p.z() < 0. || p.z() > self.height || phi > self.phi_max {
+                return None;
+            }
+            Some((t, p, phi))
+        })
+    }
+}
+
+impl Object for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let side = self.intersect_side(ray, t_min, t_max).map(|(t, p, phi)| {
+            let outward = vec3![p.x(), p.y(), 0.] / self.radius;
+            oriented_hit(ray, t, outward, (phi / self.phi_max, p.z() / self.height), &self.mat)
+        });
+
+        if !self.capped {
+            return side;
+        }
+
+        let o = ray.origin - self.center;
+
+        let caps = [(0., -1.), (self.height, 1.)].iter().fold(None, |acc, &(h, dir)| {
+            let cap = hit_cap(ray, &o, h, self.radius, 0., self.phi_max, t_min..=t_max).map(|(t, phi, r)| {
+                oriented_hit(ray, t, vec3![0., 0., dir], (phi / self.phi_max, r / self.radius), &self.mat)
+            });
+            closest(acc, cap)
+        });
+
+        closest(side, caps)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        if self.intersect_side(ray, t_min, t_max).is_some() {
+            return true;
+        }
+        let o = ray.origin - self.center;
+        self.capped
+            && [0., self.height]
+                .iter()
+                .any(|&h| hit_cap(ray, &o, h, self.radius, 0., self.phi_max, t_min..=t_max).is_some())
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        // caps of a partial cylinder reach in to the axis
+        let radii: &[f64] = if self.capped { &[self.radius, 0.] } else { &[self.radius] };
+        Some(swept_bb(&self.center, radii, 0., self.height, self.phi_max))
+    }
+}
+
+// cone with its base of radius at the center and its apex height above it
+pub struct Cone {
+    pub center: Point3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub phi_max: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+unsafe impl Sync for Cone {}
+unsafe impl Send for Cone {}
+
+impl Cone {
+    pub fn new(center: Point3, radius: f64, height: f64, capped: bool, mat: Arc<dyn Material>) -> Self {
+        Self {
+            center,
+            radius,
+            height,
+            capped,
+            phi_max: 2. * PI,
+            mat,
+        }
+    }
+
+    // only sweep the first `degrees` of the circle
+    pub fn with_phi_max(mut self, degrees: f64) -> Self {
+        self.phi_max = degrees.clamp(0., 360.).to_radians();
+        self
+    }
+}
+
+impl Cone {
+    // x^2 + y^2 = k (h - z)^2
+    fn k(&self) -> f64 {
+        (self.radius / self.height) * (self.radius / self.height)
+    }
+
+    // (t, point relative to center, phi) of the first hit on the sloped side
+    fn intersect_side(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, Vec3, f64)> {
+        let o = ray.origin - self.center;
+        let d = ray.dir;
+        let h = self.height;
+
+        let k = self.k();
+        let a = d.x() * d.x() + d.y() * d.y() - k * d.z() * d.z();
+        let b = 2. * (o.x() * d.x() + o.y() * d.y() + k * d.z() * (h - o.z()));
+        let c = o.x() * o.x() + o.y() * o.y() - k * (h - o.z()) * (h - o.z());
+
+        let (t0, t1) = solve_quadratic(a, b, c)?;
+        [t0, t1].iter().copied().find_map(|t| {
+            if !(t_min..=t_max).contains(&t) {
+                return None;
+            }
+            let p = o + d * t;
+            let phi = phi_of(p.x(), p.y());
+            if p.z() < 0. || p.z() > h || phi > self.phi_max {
+                return None;
+            }
+            Some((t, p, phi))
+        })
+    }
+}
+
+impl Object for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let h = self.height;
+        let side = self.intersect_side(ray, t_min, t_max).map(|(t, p, phi)| {
+            let outward = vec3![p.x(), p.y(), self.k() * (h - p.z())];
+            // the gradient vanishes at the apex, where the tip points up
+            let outward = if outward.is_zero() { vec3![0., 0., 1.] } else { outward.unit_vec() };
+            oriented_hit(ray, t, outward, (phi / self.phi_max, p.z() / h), &self.mat)
+        });
+
+        if !self.capped {
+            return side;
+        }
+
+        let o = ray.origin - self.center;
+
+        let base = hit_cap(ray, &o, 0., self.radius, 0., self.phi_max, t_min..=t_max).map(|(t, phi, r)| {
+            oriented_hit(ray, t, vec3![0., 0., -1.], (phi / self.phi_max, r / self.radius), &self.mat)
+        });
+
+        closest(side, base)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        if self.intersect_side(ray, t_min, t_max).is_some() {
+            return true;
+        }
+        let o = ray.origin - self.center;
+        self.capped && hit_cap(ray, &o, 0., self.radius, 0., self.phi_max, t_min..=t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let base = swept_bb(&self.center, &[self.radius], 0., 0., self.phi_max);
+        // the apex sits on the axis
+        let apex = AABB::from_points(&[self.center + vec3![0., 0., self.height]]);
+        Some(base.combine(&apex))
+    }
+}
+
+// flat disk (or annulus) facing +z
+pub struct Disk {
+    pub center: Point3,
+    pub radius: f64,
+    pub inner_radius: f64,
+    pub phi_max: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+unsafe impl Sync for Disk {}
+unsafe impl Send for Disk {}
+
+impl Disk {
+    pub fn new(center: Point3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Self {
+            center,
+            radius,
+            inner_radius: 0.,
+            phi_max: 2. * PI,
+            mat,
+        }
+    }
+
+    // cut a hole of the given radius out of the middle
+    pub fn with_inner_radius(mut self, inner_radius: f64) -> Self {
+        self.inner_radius = inner_radius;
+        self
+    }
+
+    // only sweep the first `degrees` of the circle
+    pub fn with_phi_max(mut self, degrees: f64) -> Self {
+        self.phi_max = degrees.clamp(0., 360.).to_radians();
+        self
+    }
+}
+
+impl Object for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let o = ray.origin - self.center;
+        let (t, phi, r) = hit_cap(ray, &o, 0., self.radius, self.inner_radius, self.phi_max, t_min..=t_max)?;
+
+        let v = (self.radius - r) / (self.radius - self.inner_radius);
+        Some(oriented_hit(ray, t, vec3![0., 0., 1.], (phi / self.phi_max, v), &self.mat))
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let o = ray.origin - self.center;
+        hit_cap(ray, &o, 0., self.radius, self.inner_radius, self.phi_max, t_min..=t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        // a zero inner radius pulls in the center for pie slices
+        Some(swept_bb(&self.center, &[self.radius, self.inner_radius], 0., 0., self.phi_max))
+    }
+}
+
+#[test]
+fn test_quadric_hits() {
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    let down = vec3![0., 0., -1.];
+
+    let cylinder = Cylinder::new(vec3![0., 0., 0.], 1., 2., true, mat.clone());
+    let side = cylinder
+        .hit(&Ray::new(vec3![5., 0., 1.], vec3![-1., 0., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((side.t - 4.).abs() < 1e-9);
+    assert!((side.normal - vec3![1., 0., 0.]).is_zero());
+    assert!(side.front_face);
+
+    let top = cylinder.hit(&Ray::new(vec3![0.5, 0., 5.], down), T_MIN, T_MAX).unwrap();
+    assert!((top.t - 3.).abs() < 1e-9);
+    assert!((top.normal - vec3![0., 0., 1.]).is_zero());
+
+    // from inside an open cylinder we see the back of the wall
+    let open = Cylinder::new(vec3![0., 0., 0.], 1., 2., false, mat.clone());
+    let inside = open
+        .hit(&Ray::new(vec3![0., 0., 1.], vec3![0., 1., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!(!inside.front_face);
+    assert!((inside.normal - vec3![0., -1., 0.]).is_zero());
+    assert!(open.hit(&Ray::new(vec3![0., 0., 5.], down), T_MIN, T_MAX).is_none());
+
+    // half a cylinder only covers positive y
+    let half = Cylinder::new(vec3![0., 0., 0.], 1., 2., false, mat.clone()).with_phi_max(180.);
+    let bb = half.bounding_box().unwrap();
+    assert!((bb.start.y()).abs() < 1e-3);
+    assert!(half
+        .hit(&Ray::new(vec3![0., -5., 1.], vec3![0., 1., 0.]), T_MIN, T_MAX)
+        .is_some_and(|hit| (hit.t - 6.).abs() < 1e-9));
+
+    // a thin capped wedge still bounds the caps where they meet the axis
+    let wedge = Cylinder::new(vec3![0., 0., 0.], 1., 2., true, mat.clone()).with_phi_max(45.);
+    let bb = wedge.bounding_box().unwrap();
+    assert!(bb.start.x() <= 0. && bb.start.y() <= 0.);
+
+    let cone = Cone::new(vec3![0., 0., 0.], 1., 1., true, mat.clone());
+    let hit = cone.hit(&Ray::new(vec3![0.25, 0., 5.], down), T_MIN, T_MAX).unwrap();
+    assert!((hit.t - 4.25).abs() < 1e-9);
+    assert!((hit.normal - vec3![1., 0., 1.].unit_vec()).is_zero());
+    assert!((cone.bounding_box().unwrap().end.z() - 1.).abs() < 1e-3);
+    let apex = cone.hit(&Ray::new(vec3![0., 0., 5.], down), T_MIN, T_MAX).unwrap();
+    assert!((apex.normal - vec3![0., 0., 1.]).is_zero());
+
+    let disk = Disk::new(vec3![0., 0., 1.], 1., mat.clone()).with_inner_radius(0.5);
+    assert!(disk.hit(&Ray::new(vec3![0.25, 0., 5.], down), T_MIN, T_MAX).is_none());
+    assert!((disk.hit(&Ray::new(vec3![0.75, 0., 5.], down), T_MIN, T_MAX).unwrap().t - 4.).abs() < 1e-9);
+
+    // shadow rays agree with hit, caps included
+    let wedge = Cylinder::new(vec3![0., 0., 0.], 1., 2., true, mat).with_phi_max(90.);
+    let objects: [&dyn Object; 4] = [&cylinder, &wedge, &cone, &disk];
+    for obj in objects {
+        for x in [-0.5, 0.25, 0.75, 1.5] {
+            for y in [-0.25, 0.25] {
+                let ray = Ray::new(vec3![x, y, 5.], down);
+                assert_eq!(obj.occluded(&ray, T_MIN, T_MAX), obj.hit(&ray, T_MIN, T_MAX).is_some());
+                assert!(!obj.occluded(&ray, T_MIN, 2.9));
+            }
+        }
+    }
+}
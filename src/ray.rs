@@ -4,13 +4,20 @@ use crate::vec3::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub dir: Vec3,
+    // moment in the shutter interval the ray was cast at
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, dir: Vec3) -> Ray {
+        Self::with_time(origin, dir, 0.)
+    }
+
+    pub fn with_time(origin: Vec3, dir: Vec3, time: f64) -> Ray {
         Ray {
             origin,
             dir: dir.unit_vec(),
+            time,
         }
     }
 
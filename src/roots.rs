@@ -0,0 +1,192 @@
+// real roots of a x^2 + b x + c in ascending order, a linear equation gives
+// the same root twice
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+
+    let disc = b * b - 4. * a * c;
+    if disc < 0. {
+        return None;
+    }
+
+    // avoids cancellation between b and the root of the discriminant
+    // https://en.wikipedia.org/wiki/Loss_of_significance#Instability_of_the_quadratic_equation
+    let q = -0.5 * (b + b.signum() * disc.sqrt());
+    if q == 0. {
+        return Some((0., 0.));
+    }
+
+    let (t0, t1) = (q / a, c / q);
+    Some(if t0 < t1 { (t0, t1) } else { (t1, t0) })
+}
+
+// close enough to zero to treat as an exact root or coefficient
+const EPS: f64 = 1e-9;
+
+// real roots of x^3 + a x^2 + b x + c, unordered
+// https://en.wikipedia.org/wiki/Cubic_equation#Trigonometric_and_hyperbolic_solutions
+fn solve_normed_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // substitute x = y - a / 3 for the depressed y^3 + 3 p y + 2 q
+    let sq_a = a * a;
+    let p = (-sq_a / 3. + b) / 3.;
+    let q = (2. / 27. * a * sq_a - a * b / 3. + c) / 2.;
+
+    let cb_p = p * p * p;
+    let disc = q * q + cb_p;
+
+    let mut roots = if disc.abs() < EPS {
+        if q.abs() < EPS {
+            vec![0.]
+        } else {
+            let u = (-q).cbrt();
+            vec![2. * u, -u]
+        }
+    } else if disc < 0. {
+        // three distinct real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1., 1.).acos() / 3.;
+        let t = 2. * (-p).sqrt();
+        let third = std::f64::consts::PI / 3.;
+        vec![t * phi.cos(), -t * (phi + third).cos(), -t * (phi - third).cos()]
+    } else {
+        let sqrt_disc = disc.sqrt();
+        vec![(sqrt_disc - q).cbrt() - (sqrt_disc + q).cbrt()]
+    };
+
+    for r in roots.iter_mut() {
+        *r -= a / 3.;
+    }
+    roots
+}
+
+// real roots of a x^3 + b x^2 + c x + d in ascending order
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a.abs() < EPS {
+        return match solve_quadratic(b, c, d) {
+            Some((t0, t1)) if t0 == t1 => vec![t0],
+            Some((t0, t1)) => vec![t0, t1],
+            None => vec![],
+        };
+    }
+
+    let mut roots = solve_normed_cubic(b / a, c / a, d / a);
+    for r in roots.iter_mut() {
+        *r = polish(&[a, b, c, d], *r);
+    }
+    sorted(roots)
+}
+
+// real roots of a x^4 + b x^3 + c x^2 + d x + e in ascending order, using
+// Ferrari's method and then a few newton steps to clean up the precision
+// lost in the resolvent cubic
+// https://en.wikipedia.org/wiki/Quartic_function#Ferrari's_solution
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a.abs() < EPS {
+        return solve_cubic(b, c, d, e);
+    }
+
+    let (a3, a2, a1, a0) = (b / a, c / a, d / a, e / a);
+
+    // substitute x = y - a3 / 4 for the depressed y^4 + p y^2 + q y + r
+    let sq_a = a3 * a3;
+    let p = -3. / 8. * sq_a + a2;
+    let q = sq_a * a3 / 8. - a3 * a2 / 2. + a1;
+    let r = -3. / 256. * sq_a * sq_a + sq_a * a2 / 16. - a3 * a1 / 4. + a0;
+
+    let mut roots = Vec::with_capacity(4);
+    if r.abs() < EPS {
+        // y (y^3 + p y + q) = 0
+        roots.push(0.);
+        roots.extend(solve_normed_cubic(0., p, q));
+    } else {
+        // any real root of the resolvent cubic splits the quartic into two quadratics
+        let z = solve_normed_cubic(-p / 2., -r, r * p / 2. - q * q / 8.)[0];
+
+        let u = z * z - r;
+        let v = 2. * z - p;
+        let sqrt_or_zero = |x: f64| {
+            if x.abs() < EPS {
+                Some(0.)
+            } else if x > 0. {
+                Some(x.sqrt())
+            } else {
+                None
+            }
+        };
+
+        if let (Some(u), Some(v)) = (sqrt_or_zero(u), sqrt_or_zero(v)) {
+            let v = if q < 0. { -v } else { v };
+            for (lin, con) in [(v, z - u), (-v, z + u)].iter() {
+                match solve_quadratic(1., *lin, *con) {
+                    Some((t0, t1)) if t0 == t1 => roots.push(t0),
+                    Some((t0, t1)) => roots.extend([t0, t1].iter()),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    let coeffs = [a, b, c, d, e];
+    let roots = roots
+        .into_iter()
+        .map(|y| polish(&coeffs, y - a3 / 4.))
+        .collect();
+    sorted(roots)
+}
+
+// evaluates the polynomial and its derivative, highest coefficient first
+fn eval(coeffs: &[f64], x: f64) -> (f64, f64) {
+    coeffs.iter().fold((0., 0.), |(f, df), c| (f * x + c, df * x + f))
+}
+
+fn polish(coeffs: &[f64], mut x: f64) -> f64 {
+    for _ in 0..4 {
+        let (f, df) = eval(coeffs, x);
+        if df.abs() < EPS {
+            break;
+        }
+        let next = x - f / df;
+        // only accept steps that improve the residual
+        if eval(coeffs, next).0.abs() >= f.abs() {
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    roots.dedup_by(|a, b| (*a - *b).abs() < 1e-7);
+    roots
+}
+
+#[test]
+fn test_known_roots() {
+    let close = |got: Vec<f64>, want: &[f64]| {
+        assert_eq!(got.len(), want.len(), "{:?} vs {:?}", got, want);
+        for (g, w) in got.iter().zip(want) {
+            assert!((g - w).abs() < 1e-7, "{:?} vs {:?}", got, want);
+        }
+    };
+
+    close(solve_cubic(1., -6., 11., -6.), &[1., 2., 3.]);
+    close(solve_cubic(2., 0., 0., -16.), &[2.]);
+    close(solve_cubic(1., -3., 3., -1.), &[1.]);
+
+    // (x - 1)(x - 2)(x - 3)(x - 4)
+    close(solve_quartic(1., -10., 35., -50., 24.), &[1., 2., 3., 4.]);
+    // (x + 3)(x - 1)^2(x - 2)
+    close(solve_quartic(1., -1., -7., 13., -6.), &[-3., 1., 2.]);
+    // (x^2 + 1)(x - 0.5)(x + 0.25), scaled
+    close(solve_quartic(8., -2., 7., -2., -1.), &[-0.25, 0.5]);
+    // roots spread over several orders of magnitude, like a torus seen from afar
+    // (x - 0.001)(x - 1)(x - 10)(x - 100)
+    close(solve_quartic(1., -111.001, 1110.111, -1001.11, 1.), &[0.001, 1., 10., 100.]);
+    close(solve_quartic(1., 0., 0., 0., 1.), &[]);
+    close(solve_quartic(0., 1., -6., 11., -6.), &[1., 2., 3.]);
+}
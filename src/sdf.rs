@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::quadric::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+// signed distance to a surface, negative inside
+pub type Distance = Arc<dyn Fn(&Point3) -> f64 + Send + Sync>;
+
+const MAX_STEPS: usize = 512;
+// how close counts as on the surface
+const SURFACE_EPS: f64 = 1e-5;
+// keeps the march moving when it starts exactly on the surface
+const MIN_STEP: f64 = 1e-7;
+
+// shape given by a distance function, found by sphere tracing
+// https://iquilezles.org/articles/distfunctions/
+pub struct Sdf {
+    pub dist: Distance,
+    // must contain the whole surface, marching only happens inside it
+    pub bb: AABB,
+    pub mat: Arc<dyn Material>,
+}
+
+unsafe impl Sync for Sdf {}
+unsafe impl Send for Sdf {}
+
+impl Sdf {
+    pub fn new(dist: Distance, bb: AABB, mat: Arc<dyn Material>) -> Self {
+        Self { dist, bb, mat }
+    }
+
+    // central differences of the distance field, points out of the shape
+    fn gradient(&self, p: &Point3) -> Vec3 {
+        let h = 1e-6;
+        let mut g = Vec3::empty();
+        for a in 0..3 {
+            let mut offset = Vec3::empty();
+            offset[a] = h;
+            g[a] = (self.dist)(&(*p + offset)) - (self.dist)(&(*p - offset));
+        }
+        g.unit_vec()
+    }
+
+    // sphere traces the part of the ray inside the bounding box
+    fn march(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let (t0, t1) = self.bb.hit_span(ray, t_min, t_max)?;
+
+        let mut t = t0;
+        // a ray leaving the surface at a grazing angle starts out closer than
+        // SURFACE_EPS, so hits only count once the march is closing in
+        let mut last = 0.;
+        for _ in 0..MAX_STEPS {
+            // stepping by |d| works from inside the shape too
+            let d = (self.dist)(&ray.cast(t)).abs();
+            if d < SURFACE_EPS && d <= last {
+                return Some(t);
+            }
+            last = d;
+            t += d.max(MIN_STEP);
+            if t > t1 {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+impl Object for Sdf {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let t = self.march(ray, t_min, t_max)?;
+        let outward = self.gradient(&ray.cast(t));
+        Some(oriented_hit(ray, t, outward, (0., 0.), &self.mat))
+    }
+
+    // skips the six extra distance evaluations for the normal
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.march(ray, t_min, t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bb)
+    }
+}
+
+pub fn sd_sphere(center: Point3, radius: f64) -> Distance {
+    Arc::new(move |p: &Point3| (*p - center).mag() - radius)
+}
+
+fn box_distance(p: &Point3, center: &Point3, half: &Vec3) -> f64 {
+    let local = *p - *center;
+    let q = vec3![
+        local.x().abs() - half.x(),
+        local.y().abs() - half.y(),
+        local.z().abs() - half.z()
+    ];
+    let outside = vec3![q.x().max(0.), q.y().max(0.), q.z().max(0.)].mag();
+    let inside = q.x().max(q.y()).max(q.z()).min(0.);
+    outside + inside
+}
+
+// axis aligned box with the given half extents
+pub fn sd_box(center: Point3, half: Vec3) -> Distance {
+    Arc::new(move |p: &Point3| box_distance(p, &center, &half))
+}
+
+// box of the same outer size with edges rounded off by radius
+pub fn sd_round_box(center: Point3, half: Vec3, radius: f64) -> Distance {
+    let inner = half - Vec3::of_scalar(radius);
+    Arc::new(move |p: &Point3| box_distance(p, &center, &inner) - radius)
+}
+
+// segment from a to b thickened by radius
+pub fn sd_capsule(a: Point3, b: Point3, radius: f64) -> Distance {
+    Arc::new(move |p: &Point3| {
+        let pa = *p - a;
+        let ba = b - a;
+        let h = (pa.dot(&ba) / ba.mag_squared()).clamp(0., 1.);
+        (pa - ba * h).mag() - radius
+    })
+}
+
+// union blended over a distance of roughly k
+pub fn op_smooth_union(a: Distance, b: Distance, k: f64) -> Distance {
+    Arc::new(move |p: &Point3| {
+        let (d1, d2) = (a(p), b(p));
+        let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0., 1.);
+        d2 + (d1 - d2) * h - k * h * (1. - h)
+    })
+}
+
+// carves cut out of base, blended over a distance of roughly k
+pub fn op_smooth_subtraction(base: Distance, cut: Distance, k: f64) -> Distance {
+    Arc::new(move |p: &Point3| {
+        let (d1, d2) = (cut(p), base(p));
+        let h = (0.5 - 0.5 * (d2 + d1) / k).clamp(0., 1.);
+        d2 + (-d1 - d2) * h + k * h * (1. - h)
+    })
+}
+
+#[test]
+fn test_sdf_hit() {
+    use crate::*;
+
+    let mat: Arc<dyn Material> = Arc::new(Lambert {
+        albedo: Color::white(),
+    });
+    let bb = AABB::new(vec3![-2., -2., -2.], vec3![2., 2., 2.]);
+
+    let sphere = Sdf::new(sd_sphere(vec3![0., 0., 0.], 1.), bb, mat.clone());
+    let hit = sphere
+        .hit(&Ray::new(vec3![0., 0., 5.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 4.).abs() < 1e-4);
+    assert!((hit.normal - vec3![0., 0., 1.]).mag() < 1e-4);
+    assert!(hit.front_face);
+
+    let inside = sphere
+        .hit(&Ray::new(vec3![0., 0., 0.], vec3![1., 0., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((inside.t - 1.).abs() < 1e-4);
+    assert!(!inside.front_face);
+
+    // a sphere with a box bitten out of its top
+    let bitten = op_smooth_subtraction(
+        sd_sphere(vec3![0., 0., 0.], 1.),
+        sd_box(vec3![0., 0., 1.], vec3![2., 2., 0.5]),
+        0.01,
+    );
+    let shape = Sdf::new(bitten, bb, mat.clone());
+    let hit = shape
+        .hit(&Ray::new(vec3![0., 0., 5.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 4.5).abs() < 1e-2);
+
+    // bounces leaving the surface at grazing angles don't hit it again
+    let top = sphere
+        .hit(&Ray::new(vec3![0., 0., 5.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    let analytic = Sphere::new(vec3![0., 0., 0.], 1., Color::white(), mat);
+    for z in [0.001, 0.01, 0.05, 0.1, 0.2] {
+        let bounce = Ray::new(top.point, vec3![1., 0., z]);
+        assert!(analytic.hit(&bounce, T_MIN, T_MAX).is_none());
+        assert!(sphere.hit(&bounce, T_MIN, T_MAX).is_none(), "{}", z);
+    }
+
+    let round = sd_round_box(vec3![0., 0., 0.], vec3![1., 1., 1.], 0.25);
+    assert!((round(&vec3![2., 0., 0.]) - 1.).abs() < 1e-9);
+    let capsule = sd_capsule(vec3![0., 0., 0.], vec3![0., 0., 2.], 0.5);
+    assert!((capsule(&vec3![1., 0., 1.]) - 0.5).abs() < 1e-9);
+    let union = op_smooth_union(sd_sphere(vec3![-1., 0., 0.], 1.), sd_sphere(vec3![1., 0., 0.], 1.), 0.5);
+    assert!(union(&vec3![0., 0., 0.]) < 0.);
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::material::*;
+use crate::mesh::*;
+use crate::vec3::*;
+
+// unordered vertex pair used to key edges
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+// weight 0 gives the smooth rule's position and 1 the sharp one
+fn blend(smooth: Point3, sharp: Point3, weight: f64) -> Point3 {
+    (1. - weight) * smooth + weight * sharp
+}
+
+// (other vertex, sharpness) for each sharp edge at a vertex
+type SharpEdges = Vec<(usize, f64)>;
+
+struct Edge {
+    faces: Vec<usize>,
+    // index of the edge point in the refined mesh
+    point: usize,
+}
+
+// polygon mesh for subdivision, faces are usually triangles (Loop) or quads
+// (Catmull-Clark). creased edges carry a sharpness that drops by one every
+// level, so a sharpness of 2 stays sharp for two levels and then smooths out.
+// below one a crease is semi-sharp and blends the sharp and smooth rules.
+// boundary edges are always sharp
+#[derive(Clone)]
+pub struct SubdivMesh {
+    pub vertices: Vec<Point3>,
+    pub faces: Vec<Vec<usize>>,
+    pub creases: HashMap<(usize, usize), f64>,
+}
+
+impl SubdivMesh {
+    pub fn new(vertices: Vec<Point3>, faces: Vec<Vec<usize>>) -> Self {
+        for face in faces.iter() {
+            assert!(face.len() >= 3, "faces need at least 3 vertices");
+            assert!(face.iter().all(|&i| i < vertices.len()));
+        }
+
+        Self {
+            vertices,
+            faces,
+            creases: HashMap::new(),
+        }
+    }
+
+    // welds vertices that share a position, loaders split them whenever the
+    // normals or uvs differ which would tear the surface apart
+    pub fn from_triangles(mesh: &TriangleMesh) -> Self {
+        let mut welded: HashMap<[u64; 3], usize> = HashMap::new();
+        let mut vertices = Vec::new();
+        let remap: Vec<usize> = mesh
+            .vertices
+            .iter()
+            .map(|v| {
+                let key = [v.x().to_bits(), v.y().to_bits(), v.z().to_bits()];
+                *welded.entry(key).or_insert_with(|| {
+                    vertices.push(*v);
+                    vertices.len() - 1
+                })
+            })
+            .collect();
+
+        let faces = mesh
+            .indices
+            .iter()
+            .map(|tri| tri.iter().map(|&i| remap[i]).collect())
+            .collect();
+
+        Self::new(vertices, faces)
+    }
+
+    pub fn with_crease(mut self, a: usize, b: usize, sharpness: f64) -> Self {
+        self.creases.insert(edge_key(a, b), sharpness);
+        self
+    }
+
+    pub fn is_triangulated(&self) -> bool {
+        self.faces.iter().all(|face| face.len() == 3)
+    }
+
+    // Loop for pure triangle meshes, Catmull-Clark for everything else
+    pub fn subdivide(&self, levels: usize) -> Self {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = mesh.refine();
+        }
+        mesh
+    }
+
+    fn refine(&self) -> Self {
+        if self.is_triangulated() {
+            self.loop_step()
+        } else {
+            self.catmull_clark_step()
+        }
+    }
+
+    fn edges(&self, first_point: usize) -> HashMap<(usize, usize), Edge> {
+        let mut edges: HashMap<(usize, usize), Edge> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for i in 0..face.len() {
+                let key = edge_key(face[i], face[(i + 1) % face.len()]);
+                let next = first_point + edges.len();
+                edges
+                    .entry(key)
+                    .or_insert(Edge {
+                        faces: vec![],
+                        point: next,
+                    })
+                    .faces
+                    .push(f);
+            }
+        }
+        edges
+    }
+
+    fn sharpness(&self, key: &(usize, usize), edge: &Edge) -> f64 {
+        // boundary and non manifold edges can't be smoothed across
+        if edge.faces.len() != 2 {
+            return f64::INFINITY;
+        }
+        self.creases.get(key).copied().unwrap_or(0.)
+    }
+
+    // neighbours of every vertex, and the ones joined to it by a sharp edge
+    // along with its sharpness
+    fn neighbours(&self, edges: &HashMap<(usize, usize), Edge>) -> (Vec<Vec<usize>>, Vec<SharpEdges>) {
+        let mut all = vec![vec![]; self.vertices.len()];
+        let mut sharp = vec![vec![]; self.vertices.len()];
+        for (&(a, b), edge) in edges.iter() {
+            all[a].push(b);
+            all[b].push(a);
+            let s = self.sharpness(&(a, b), edge);
+            if s > 0. {
+                sharp[a].push((b, s));
+                sharp[b].push((a, s));
+            }
+        }
+        (all, sharp)
+    }
+
+    // new position of vertex v, smooth is the scheme's rule for it
+    fn refined_vertex(&self, v: usize, sharp: &[(usize, f64)], smooth: impl FnOnce() -> Point3) -> Point3 {
+        let crease = match sharp.len() {
+            // smooth vertex, or a dart where a crease fades out
+            0 | 1 => return smooth(),
+            2 => 0.75 * self.vertices[v] + 0.125 * (self.vertices[sharp[0].0] + self.vertices[sharp[1].0]),
+            // corners stay put
+            _ => self.vertices[v],
+        };
+
+        // semi-sharp creases only pull part of the way
+        let weight = sharp.iter().map(|&(_, s)| s.min(1.)).sum::<f64>() / sharp.len() as f64;
+        if weight >= 1. {
+            crease
+        } else {
+            blend(smooth(), crease, weight)
+        }
+    }
+
+    // each refined crease edge inherits the sharpness of its parent minus one
+    fn child_creases(&self, edges: &HashMap<(usize, usize), Edge>) -> HashMap<(usize, usize), f64> {
+        let mut creases = HashMap::new();
+        for (key, &sharpness) in self.creases.iter() {
+            if sharpness <= 1. {
+                continue;
+            }
+            if let Some(edge) = edges.get(key) {
+                creases.insert(edge_key(key.0, edge.point), sharpness - 1.);
+                creases.insert(edge_key(key.1, edge.point), sharpness - 1.);
+            }
+        }
+        creases
+    }
+
+    // https://en.wikipedia.org/wiki/Loop_subdivision_surface
+    fn loop_step(&self) -> Self {
+        let n = self.vertices.len();
+        let edges = self.edges(n);
+        let (all, sharp) = self.neighbours(&edges);
+
+        let mut vertices = vec![Vec3::empty(); n + edges.len()];
+        for v in 0..n {
+            vertices[v] = self.refined_vertex(v, &sharp[v], || {
+                let k = all[v].len();
+                let beta = if k == 3 { 3. / 16. } else { 3. / (8. * k as f64) };
+                let sum = all[v].iter().fold(Vec3::empty(), |acc, &u| acc + self.vertices[u]);
+                (1. - k as f64 * beta) * self.vertices[v] + beta * sum
+            });
+        }
+
+        for (key, edge) in edges.iter() {
+            let (a, b) = (self.vertices[key.0], self.vertices[key.1]);
+            let s = self.sharpness(key, edge);
+            vertices[edge.point] = if s >= 1. {
+                0.5 * (a + b)
+            } else {
+                // the vertices facing the edge in its two triangles
+                let opposite = edge.faces.iter().fold(Vec3::empty(), |acc, &f| {
+                    let far = self.faces[f].iter().find(|&&i| i != key.0 && i != key.1).unwrap();
+                    acc + self.vertices[*far]
+                });
+                blend(0.375 * (a + b) + 0.125 * opposite, 0.5 * (a + b), s)
+            };
+        }
+
+        let mut faces = Vec::with_capacity(self.faces.len() * 4);
+        for face in self.faces.iter() {
+            let (a, b, c) = (face[0], face[1], face[2]);
+            let ab = edges[&edge_key(a, b)].point;
+            let bc = edges[&edge_key(b, c)].point;
+            let ca = edges[&edge_key(c, a)].point;
+            faces.push(vec![a, ab, ca]);
+            faces.push(vec![ab, b, bc]);
+            faces.push(vec![ca, bc, c]);
+            faces.push(vec![ab, bc, ca]);
+        }
+
+        Self {
+            vertices,
+            faces,
+            creases: self.child_creases(&edges),
+        }
+    }
+
+    // https://en.wikipedia.org/wiki/Catmull%E2%80%93Clark_subdivision_surface
+    fn catmull_clark_step(&self) -> Self {
+        let n = self.vertices.len();
+        let edges = self.edges(n);
+        let (all, sharp) = self.neighbours(&edges);
+        let first_face = n + edges.len();
+
+        let face_points: Vec<Point3> = self
+            .faces
+            .iter()
+            .map(|face| {
+                let sum = face.iter().fold(Vec3::empty(), |acc, &i| acc + self.vertices[i]);
+                sum / face.len() as f64
+            })
+            .collect();
+
+        let mut vertex_faces = vec![vec![]; n];
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face.iter() {
+                vertex_faces[v].push(f);
+            }
+        }
+
+        let mut vertices = vec![Vec3::empty(); first_face + self.faces.len()];
+        for v in 0..n {
+            vertices[v] = self.refined_vertex(v, &sharp[v], || {
+                let k = all[v].len() as f64;
+                let f = vertex_faces[v].iter().fold(Vec3::empty(), |acc, &f| acc + face_points[f])
+                    / vertex_faces[v].len() as f64;
+                let r = all[v].iter().fold(Vec3::empty(), |acc, &u| {
+                    acc + 0.5 * (self.vertices[v] + self.vertices[u])
+                }) / k;
+                (f + 2. * r + (k - 3.) * self.vertices[v]) / k
+            });
+        }
+
+        for (key, edge) in edges.iter() {
+            let (a, b) = (self.vertices[key.0], self.vertices[key.1]);
+            let s = self.sharpness(key, edge);
+            vertices[edge.point] = if s >= 1. {
+                0.5 * (a + b)
+            } else {
+                let smooth = (a + b + face_points[edge.faces[0]] + face_points[edge.faces[1]]) / 4.;
+                blend(smooth, 0.5 * (a + b), s)
+            };
+        }
+
+        for (f, p) in face_points.into_iter().enumerate() {
+            vertices[first_face + f] = p;
+        }
+
+        let mut faces = Vec::with_capacity(self.faces.len() * 4);
+        for (f, face) in self.faces.iter().enumerate() {
+            let k = face.len();
+            for i in 0..k {
+                let prev = face[(i + k - 1) % k];
+                let next = face[(i + 1) % k];
+                faces.push(vec![
+                    face[i],
+                    edges[&edge_key(face[i], next)].point,
+                    first_face + f,
+                    edges[&edge_key(prev, face[i])].point,
+                ]);
+            }
+        }
+
+        Self {
+            vertices,
+            faces,
+            creases: self.child_creases(&edges),
+        }
+    }
+
+    // fans every face into triangles, with area weighted vertex normals for
+    // smooth shading
+    pub fn to_triangle_mesh(&self, mat: Arc<dyn Material>) -> TriangleMesh {
+        let mut indices = Vec::new();
+        for face in self.faces.iter() {
+            for i in 1..face.len() - 1 {
+                indices.push([face[0], face[i], face[i + 1]]);
+            }
+        }
+
+        let mut normals = vec![Vec3::empty(); self.vertices.len()];
+        for [a, b, c] in indices.iter() {
+            let (va, vb, vc) = (self.vertices[*a], self.vertices[*b], self.vertices[*c]);
+            // not normalized, so larger triangles count for more
+            let n = (vb - va).cross(&(vc - va));
+            for i in [a, b, c] {
+                normals[*i] = normals[*i] + n;
+            }
+        }
+        let normals = normals
+            .into_iter()
+            .map(|n| if n.is_zero() { n } else { n.unit_vec() })
+            .collect();
+
+        TriangleMesh::new(self.vertices.clone(), normals, vec![], indices, mat)
+    }
+}
+
+#[test]
+fn test_subdivision() {
+    let close = |a: Vec3, b: Vec3| (a - b).is_zero();
+
+    // octahedron, every vertex has valence 4
+    let octahedron = SubdivMesh::new(
+        vec![
+            vec3![1., 0., 0.],
+            vec3![-1., 0., 0.],
+            vec3![0., 1., 0.],
+            vec3![0., -1., 0.],
+            vec3![0., 0., 1.],
+            vec3![0., 0., -1.],
+        ],
+        vec![
+            vec![0, 2, 4],
+            vec![2, 1, 4],
+            vec![1, 3, 4],
+            vec![3, 0, 4],
+            vec![2, 0, 5],
+            vec![1, 2, 5],
+            vec![3, 1, 5],
+            vec![0, 3, 5],
+        ],
+    );
+    let refined = octahedron.subdivide(1);
+    assert_eq!(refined.faces.len(), 32);
+    assert_eq!(refined.vertices.len(), 6 + 12);
+    // (1 - 4 * 3/32) v + 3/32 * (sum of the neighbours, which is zero)
+    assert!(close(refined.vertices[0], vec3![0.625, 0., 0.]));
+    assert_eq!(octahedron.subdivide(3).faces.len(), 8 * 64);
+
+    let cube_vertices = (0..8)
+        .map(|i| {
+            let s = |bit: usize| if i & bit != 0 { 1. } else { -1. };
+            vec3![s(1), s(2), s(4)]
+        })
+        .collect::<Vec<_>>();
+    let cube_faces = vec![
+        vec![0, 2, 3, 1],
+        vec![4, 5, 7, 6],
+        vec![0, 1, 5, 4],
+        vec![2, 6, 7, 3],
+        vec![0, 4, 6, 2],
+        vec![1, 3, 7, 5],
+    ];
+    let cube = SubdivMesh::new(cube_vertices.clone(), cube_faces.clone());
+    let refined = cube.subdivide(1);
+    assert_eq!(refined.vertices.len(), 8 + 12 + 6);
+    assert_eq!(refined.faces.len(), 24);
+    assert!(close(refined.vertices[7], vec3![5. / 9., 5. / 9., 5. / 9.]));
+
+    // creasing every edge keeps the cube's corners and edges in place for as
+    // long as the sharpness lasts
+    let mut creased = SubdivMesh::new(cube_vertices, cube_faces.clone());
+    for face in cube_faces.iter() {
+        for i in 0..4 {
+            creased = creased.with_crease(face[i], face[(i + 1) % 4], 2.);
+        }
+    }
+    let twice = creased.subdivide(2);
+    assert!(close(twice.vertices[7], vec3![1., 1., 1.]));
+    assert!(twice.vertices.iter().all(|v| (0..3).any(|a| (v[a].abs() - 1.).abs() < 1e-9)));
+    assert!(twice.creases.is_empty());
+    let smoothed = twice.subdivide(1);
+    assert!(smoothed.vertices[7].x() < 1.);
+
+    // a semi-sharp crease lands halfway between the smooth and sharp results
+    let mut semi = cube.clone();
+    for face in cube_faces.iter() {
+        for i in 0..4 {
+            semi = semi.with_crease(face[i], face[(i + 1) % 4], 0.5);
+        }
+    }
+    let refined = semi.subdivide(1);
+    assert!(close(refined.vertices[7], vec3![7. / 9., 7. / 9., 7. / 9.]));
+    assert!(refined.creases.is_empty());
+
+    let mesh = cube.subdivide(2).to_triangle_mesh(Arc::new(Lambert {
+        albedo: crate::Color::white(),
+    }));
+    assert_eq!(mesh.len(), 96 * 2);
+    assert!(mesh.normals.iter().all(|n| (n.mag() - 1.).abs() < 1e-9));
+}
@@ -0,0 +1,137 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::quadric::*;
+use crate::ray::*;
+use crate::roots::*;
+use crate::vec3::*;
+
+// ring around the z axis through center, the tube of minor_radius sweeps a
+// circle of major_radius
+pub struct Torus {
+    pub center: Point3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+unsafe impl Sync for Torus {}
+unsafe impl Send for Torus {}
+
+impl Torus {
+    pub fn new(center: Point3, major_radius: f64, minor_radius: f64, mat: Arc<dyn Material>) -> Self {
+        Self {
+            center,
+            major_radius,
+            minor_radius,
+            mat,
+        }
+    }
+
+    // first root of the quartic in [t_min, t_max]
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let big_r2 = self.major_radius * self.major_radius;
+        let small_r2 = self.minor_radius * self.minor_radius;
+
+        // start from just outside the torus so far away rays don't lose
+        // precision in the quartic
+        let o = ray.origin - self.center;
+        let d = ray.dir;
+        let shift = ((-o).dot(&d) - self.major_radius - self.minor_radius).max(0.);
+        let o = o + d * shift;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = o + t d, |d| = 1
+        let n = o.dot(&d);
+        let k = o.mag_squared() + big_r2 - small_r2;
+        let roots = solve_quartic(
+            1.,
+            4. * n,
+            4. * n * n + 2. * k - 4. * big_r2 * (d.x() * d.x() + d.y() * d.y()),
+            4. * n * k - 8. * big_r2 * (o.x() * d.x() + o.y() * d.y()),
+            k * k - 4. * big_r2 * (o.x() * o.x() + o.y() * o.y()),
+        );
+
+        roots
+            .into_iter()
+            .map(|t| t + shift)
+            .find(|t| (t_min..=t_max).contains(t))
+    }
+}
+
+impl Object for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let t = self.intersect(ray, t_min, t_max)?;
+        let big_r2 = self.major_radius * self.major_radius;
+        let small_r2 = self.minor_radius * self.minor_radius;
+
+        let p = ray.cast(t) - self.center;
+        let planar = p.x() * p.x() + p.y() * p.y();
+        // gradient of the implicit surface, points out of the tube
+        let outward = (p * (p.mag_squared() + big_r2 - small_r2) - vec3![p.x(), p.y(), 0.] * (2. * big_r2))
+            .unit_vec();
+
+        let phi = p.y().atan2(p.x());
+        let theta = p.z().atan2(planar.sqrt() - self.major_radius);
+        let uv = ((phi + PI) / (2. * PI), (theta + PI) / (2. * PI));
+
+        Some(oriented_hit(ray, t, outward, uv, &self.mat))
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(ray, t_min, t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let reach = self.major_radius + self.minor_radius;
+        let extent = vec3![reach, reach, self.minor_radius];
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+}
+
+#[test]
+fn test_torus_hit() {
+    use crate::*;
+
+    let torus = Torus::new(
+        vec3![0., 0., 1.],
+        2.,
+        0.5,
+        Arc::new(Lambert {
+            albedo: Color::white(),
+        }),
+    );
+
+    // straight through the hole
+    assert!(torus
+        .hit(&Ray::new(vec3![0., 0., 10.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .is_none());
+
+    let top = torus
+        .hit(&Ray::new(vec3![2., 0., 10.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((top.t - 8.5).abs() < 1e-6);
+    assert!((top.normal - vec3![0., 0., 1.]).is_zero());
+
+    // along the x axis from far away we hit the outer rim first
+    let side = torus
+        .hit(&Ray::new(vec3![1000., 0., 1.], vec3![-1., 0., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((side.t - 997.5).abs() < 1e-6);
+    assert!((side.normal - vec3![1., 0., 0.]).is_zero());
+    assert!(side.front_face);
+
+    // inside the tube we see its back
+    let inside = torus
+        .hit(&Ray::new(vec3![2., 0., 1.], vec3![1., 0., 0.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((inside.t - 0.5).abs() < 1e-6);
+    assert!(!inside.front_face);
+
+    let ray = Ray::new(vec3![2., 0., 10.], vec3![0., 0., -1.]);
+    assert!(torus.occluded(&ray, T_MIN, T_MAX));
+    assert!(!torus.occluded(&ray, T_MIN, 8.4));
+    assert!(!torus.occluded(&Ray::new(vec3![0., 0., 10.], vec3![0., 0., -1.]), T_MIN, T_MAX));
+}
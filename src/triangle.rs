@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::vec3::*;
+use crate::*;
+
+pub struct Triangle {
+    pub v0: Point3,
+    pub v1: Point3,
+    pub v2: Point3,
+    // per vertex normals, interpolated for smooth shading
+    pub normals: Option<[Vec3; 3]>,
+    pub mat: Arc<dyn Material>,
+}
+
+unsafe impl Sync for Triangle {}
+unsafe impl Send for Triangle {}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat: Arc<dyn Material>) -> Self {
+        Self {
+            v0,
+            v1,
+            v2,
+            normals: None,
+            mat,
+        }
+    }
+
+    pub fn with_normals(
+        v0: Point3,
+        v1: Point3,
+        v2: Point3,
+        normals: [Vec3; 3],
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            v0,
+            v1,
+            v2,
+            normals: Some(normals),
+            mat,
+        }
+    }
+}
+
+// https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+// returns (t, u, v) where u and v are the barycentric weights of v1 and v2
+pub fn intersect_triangle(ray: &Ray, v0: &Point3, v1: &Point3, v2: &Point3, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let e1 = *v1 - *v0;
+    let e2 = *v2 - *v0;
+
+    let p = ray.dir.cross(&e2);
+    let det = e1.dot(&p);
+
+    // ray is parallel to the triangle
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1. / det;
+
+    let s = ray.origin - *v0;
+    let u = s.dot(&p) * inv_det;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&e1);
+    let v = ray.dir.dot(&q) * inv_det;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+
+    let t = e2.dot(&q) * inv_det;
+    if !(t_min..=t_max).contains(&t) {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+// builds the hit shared by triangles and meshes, front_face follows Sphere
+pub fn triangle_hit(
+    ray: &Ray,
+    verts: [&Point3; 3],
+    normals: Option<[&Vec3; 3]>,
+    t: f64,
+    bary: (f64, f64),
+    uv: (f64, f64),
+    mat: &Arc<dyn Material>,
+) -> RayHit {
+    let (u, v) = bary;
+    let geometric = (*verts[1] - *verts[0]).cross(&(*verts[2] - *verts[0])).unit_vec();
+
+    let shading = match normals {
+        Some([n0, n1, n2]) => ((1. - u - v) * *n0 + u * *n1 + v * *n2).unit_vec(),
+        None => geometric,
+    };
+
+    let (normal, front_face) = if geometric.dot(&ray.dir) > 0. {
+        // we are behind the triangle
+        (-shading, false)
+    } else {
+        (shading, true)
+    };
+
+    RayHit {
+        col: Color::white(),
+        point: ray.cast(t),
+        t,
+        normal,
+        front_face,
+        bary,
+        uv,
+        mat: mat.clone(),
+    }
+}
+
+impl Object for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let (t, u, v) = intersect_triangle(ray, &self.v0, &self.v1, &self.v2, t_min, t_max)?;
+
+        let normals = self.normals.as_ref().map(|n| [&n[0], &n[1], &n[2]]);
+        Some(triangle_hit(
+            ray,
+            [&self.v0, &self.v1, &self.v2],
+            normals,
+            t,
+            (u, v),
+            (u, v),
+            &self.mat,
+        ))
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        intersect_triangle(ray, &self.v0, &self.v1, &self.v2, t_min, t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::from_points(&[self.v0, self.v1, self.v2]))
+    }
+}
+
+#[test]
+fn test_triangle_hit() {
+    let tri = Triangle::new(
+        vec3![0., 0., 0.],
+        vec3![1., 0., 0.],
+        vec3![0., 1., 0.],
+        Arc::new(Lambert {
+            albedo: Color::white(),
+        }),
+    );
+
+    let hit = tri
+        .hit(&Ray::new(vec3![0.25, 0.5, 1.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!((hit.t - 1.).abs() < 1e-9);
+    assert!((hit.bary.0 - 0.25).abs() < 1e-9);
+    assert!((hit.bary.1 - 0.5).abs() < 1e-9);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, vec3![0., 0., 1.]);
+
+    let back = tri
+        .hit(&Ray::new(vec3![0.25, 0.25, -1.], vec3![0., 0., 1.]), T_MIN, T_MAX)
+        .unwrap();
+    assert!(!back.front_face);
+    assert_eq!(back.normal, vec3![0., 0., -1.]);
+
+    assert!(tri
+        .hit(&Ray::new(vec3![0.75, 0.75, 1.], vec3![0., 0., -1.]), T_MIN, T_MAX)
+        .is_none());
+}
@@ -122,6 +122,19 @@ impl Vec3 {
         Self::rand_in_unit_circle().unit_vec()
     }
 
+    // two unit vectors that together with self (assumed unit) form an
+    // orthonormal basis
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let helper = if self.x().abs() > 0.9 {
+            vec3![0., 1., 0.]
+        } else {
+            vec3![1., 0., 0.]
+        };
+        let a = self.cross(&helper).unit_vec();
+        let b = self.cross(&a);
+        (a, b)
+    }
+
     pub fn is_zero(&self) -> bool {
         let eps = 0.00001;
         for i in 0..3 {
@@ -247,3 +260,255 @@ fn test_ops() {
     assert_eq!(a.mag_squared(), 13.);
     assert_eq!(a.mag(), (13 as f64).sqrt());
 }
+
+// row major 3x3 matrix
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat3 {
+    pub m: [[f64; 3]; 3],
+}
+
+impl Mat3 {
+    pub fn new(m: [[f64; 3]; 3]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]])
+    }
+
+    pub fn of_cols(a: &Vec3, b: &Vec3, c: &Vec3) -> Self {
+        Self::new([[a[0], b[0], c[0]], [a[1], b[1], c[1]], [a[2], b[2], c[2]]])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = *self;
+        for i in 0..3 {
+            for j in 0..3 {
+                t.m[i][j] = self.m[j][i];
+            }
+        }
+        t
+    }
+
+    pub fn det(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // None if the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.det();
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        // the inverse is the adjugate over the determinant
+        let m = &self.m;
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let adj = [
+            [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+            [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+            [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+        ];
+
+        let mut inv = Self::new(adj);
+        for row in inv.m.iter_mut() {
+            for x in row.iter_mut() {
+                *x /= det;
+            }
+        }
+        Some(inv)
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut out = [[0.; 3]; 3];
+        for (i, row) in out.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..3).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Self::new(out)
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        vec3![
+            m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+            m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+            m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2]
+        ]
+    }
+}
+
+// row major 4x4 affine transform, points are column vectors with w = 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::from_parts(&Mat3::identity(), &Vec3::empty())
+    }
+
+    // linear part followed by a translation
+    pub fn from_parts(linear: &Mat3, translation: &Vec3) -> Self {
+        let mut m = [[0., 0., 0., 0.], [0., 0., 0., 0.], [0., 0., 0., 0.], [0., 0., 0., 1.]];
+        for (i, row) in m.iter_mut().take(3).enumerate() {
+            row[..3].copy_from_slice(&linear.m[i]);
+            row[3] = translation[i];
+        }
+        Self::new(m)
+    }
+
+    pub fn translate(v: &Vec3) -> Self {
+        Self::from_parts(&Mat3::identity(), v)
+    }
+
+    pub fn scale(v: &Vec3) -> Self {
+        Self::from_parts(
+            &Mat3::new([[v[0], 0., 0.], [0., v[1], 0.], [0., 0., v[2]]]),
+            &Vec3::empty(),
+        )
+    }
+
+    // counter clockwise rotation about axis, in degrees
+    pub fn rotate(axis: &Vec3, degrees: f64) -> Self {
+        // https://en.wikipedia.org/wiki/Rotation_matrix#Rotation_matrix_from_axis_and_angle
+        let a = axis.unit_vec();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1. - cos;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+
+        Self::from_parts(
+            &Mat3::new([
+                [cos + x * x * k, x * y * k - z * sin, x * z * k + y * sin],
+                [y * x * k + z * sin, cos + y * y * k, y * z * k - x * sin],
+                [z * x * k - y * sin, z * y * k + x * sin, cos + z * z * k],
+            ]),
+            &Vec3::empty(),
+        )
+    }
+
+    pub fn linear(&self) -> Mat3 {
+        let mut m = [[0.; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            row.copy_from_slice(&self.m[i][..3]);
+        }
+        Mat3::new(m)
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        vec3![self.m[0][3], self.m[1][3], self.m[2][3]]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = *self;
+        for i in 0..4 {
+            for j in 0..4 {
+                t.m[i][j] = self.m[j][i];
+            }
+        }
+        t
+    }
+
+    // general inverse by gauss jordan elimination, None if singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Self::new(inv))
+    }
+
+    // entry wise blend, points move on straight lines from self to other
+    pub fn lerp(&self, other: &Mat4, s: f64) -> Self {
+        let mut out = *self;
+        for i in 0..4 {
+            for j in 0..4 {
+                out.m[i][j] += (other.m[i][j] - self.m[i][j]) * s;
+            }
+        }
+        out
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        self.linear() * *p + self.translation()
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        self.linear() * *v
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut out = [[0.; 4]; 4];
+        for (i, row) in out.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Self::new(out)
+    }
+}
+
+#[test]
+fn test_mat_inverse() {
+    let xform = Mat4::translate(&vec3![1., -2., 3.])
+        * Mat4::rotate(&vec3![1., 1., 0.], 30.)
+        * Mat4::scale(&vec3![2., 0.5, 1.]);
+    let inv = xform.inverse().unwrap();
+
+    let p = vec3![0.3, -4., 7.];
+    assert!((inv.transform_point(&xform.transform_point(&p)) - p).is_zero());
+
+    let lin = xform.linear();
+    let v = vec3![1., 2., 3.];
+    assert!((lin.inverse().unwrap() * (lin * v) - v).is_zero());
+    assert!((lin.transpose().inverse().unwrap() * v - inv.linear().transpose() * v).is_zero());
+
+    assert!(Mat4::scale(&vec3![1., 0., 1.]).inverse().is_none());
+}
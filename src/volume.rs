@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::bounding_box::*;
+use crate::material::*;
+use crate::medium::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+// densities on a regular nx * ny * nz grid, x varies fastest
+pub struct DensityGrid {
+    pub dims: (usize, usize, usize),
+    pub data: Vec<f64>,
+    max: f64,
+}
+
+impl DensityGrid {
+    pub fn new(dims: (usize, usize, usize), data: Vec<f64>) -> Self {
+        assert_eq!(dims.0 * dims.1 * dims.2, data.len());
+        assert!(dims.0 > 0 && dims.1 > 0 && dims.2 > 0);
+        let max = data.iter().copied().fold(0., f64::max);
+        Self { dims, data, max }
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    fn at(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.dims.1 + y) * self.dims.0 + x]
+    }
+
+    // trilinear lookup at p in the unit cube, voxel centers sit at (i + 0.5) / n
+    pub fn lookup(&self, p: &Vec3) -> f64 {
+        let dims = [self.dims.0, self.dims.1, self.dims.2];
+        let mut lo = [0; 3];
+        let mut hi = [0; 3];
+        let mut frac = [0.; 3];
+        for a in 0..3 {
+            let g = (p[a] * dims[a] as f64 - 0.5).clamp(0., (dims[a] - 1) as f64);
+            lo[a] = g.floor() as usize;
+            hi[a] = (lo[a] + 1).min(dims[a] - 1);
+            frac[a] = g - lo[a] as f64;
+        }
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let row = |y: usize, z: usize| lerp(self.at(lo[0], y, z), self.at(hi[0], y, z), frac[0]);
+        let plane = |z: usize| lerp(row(lo[1], z), row(hi[1], z), frac[1]);
+        lerp(plane(lo[2]), plane(hi[2]), frac[2])
+    }
+}
+
+// smoke or clouds whose density comes from a grid stretched over bb
+pub struct GridMedium {
+    pub grid: DensityGrid,
+    pub bb: AABB,
+    // multiplies the grid values into extinction per unit distance
+    pub density_scale: f64,
+    pub phase: Arc<dyn Material>,
+}
+
+unsafe impl Sync for GridMedium {}
+unsafe impl Send for GridMedium {}
+
+impl GridMedium {
+    pub fn new(grid: DensityGrid, bb: AABB, density_scale: f64, phase: Arc<dyn Material>) -> Self {
+        Self {
+            grid,
+            bb,
+            density_scale,
+            phase,
+        }
+    }
+
+    fn density(&self, p: &Point3) -> f64 {
+        let size = self.bb.end - self.bb.start;
+        let local = *p - self.bb.start;
+        let unit = vec3![local.x() / size.x(), local.y() / size.y(), local.z() / size.z()];
+        self.grid.lookup(&unit) * self.density_scale
+    }
+
+    // steps of an exponential walk through the majorant density, the grid is
+    // only consulted at the tentative collisions
+    fn tentative_collisions<'a>(&'a self, ray: &'a Ray, t0: f64, t1: f64) -> impl Iterator<Item = (f64, f64)> + 'a {
+        let majorant = self.grid.max() * self.density_scale;
+        let mut rng = rand::thread_rng();
+        let mut t = t0;
+
+        std::iter::from_fn(move || {
+            if majorant <= 0. {
+                return None;
+            }
+            t -= (1. - rng.gen::<f64>()).ln() / majorant;
+            if t >= t1 {
+                return None;
+            }
+            Some((t, self.density(&ray.cast(t)) / majorant))
+        })
+    }
+
+    // unbiased estimate of the transmittance between t0 and t1 by ratio tracking
+    // https://jannovak.info/publications/RRTracking/index.html
+    pub fn transmittance(&self, ray: &Ray, t0: f64, t1: f64) -> f64 {
+        let (t0, t1) = match self.bb.hit_span(ray, t0, t1) {
+            Some(span) => span,
+            None => return 1.,
+        };
+
+        self.tentative_collisions(ray, t0, t1)
+            .fold(1., |tr, (_, ratio)| tr * (1. - ratio))
+    }
+}
+
+impl Object for GridMedium {
+    // delta tracking, accepts a tentative collision with probability
+    // density / majorant so the free flight distance is unbiased
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<RayHit> {
+        let (t0, t1) = self.bb.hit_span(ray, t_min, t_max)?;
+        let mut rng = rand::thread_rng();
+
+        let (t, _) = self
+            .tentative_collisions(ray, t0, t1)
+            .find(|(_, ratio)| rng.gen::<f64>() < *ratio)?;
+
+        Some(medium_hit(ray, t, &self.phase))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bb)
+    }
+}
+
+#[test]
+fn test_grid_transmittance() {
+    use crate::*;
+
+    // left half empty, right half of density 1 per unit
+    let grid = crate::loader::voxel::parse_voxels_ascii("2 1 1\n0 1\n", "test.vox").unwrap();
+    assert_eq!(grid.lookup(&vec3![0., 0.5, 0.5]), 0.);
+    assert_eq!(grid.lookup(&vec3![1., 0.5, 0.5]), 1.);
+    assert_eq!(grid.lookup(&vec3![0.5, 0.5, 0.5]), 0.5);
+
+    let medium = GridMedium::new(
+        grid,
+        AABB::new(vec3![0., 0., 0.], vec3![2., 1., 1.]),
+        1.,
+        Arc::new(Isotropic {
+            albedo: Color::white(),
+        }),
+    );
+
+    // density ramps from 0 at x = 0.5 up to 1 at x = 1.5 and stays there, so
+    // the optical depth along x is 0.5 + 0.5
+    let ray = Ray::new(vec3![-1., 0.5, 0.5], vec3![1., 0., 0.]);
+    let n = 20000;
+    let mean = (0..n).map(|_| medium.transmittance(&ray, 0., 10.)).sum::<f64>() / n as f64;
+    assert!((mean - (-1f64).exp()).abs() < 0.02, "{}", mean);
+
+    // nothing to collide with along the empty side
+    let empty = Ray::new(vec3![0.5, -1., 0.5], vec3![0., 1., 0.]);
+    assert!(medium.hit(&empty, T_MIN, T_MAX).is_none());
+
+    // collisions stay inside the caller's interval
+    for _ in 0..100 {
+        if let Some(hit) = medium.hit(&ray, 2.2, 2.8) {
+            assert!((2.2..2.8).contains(&hit.t));
+        }
+    }
+}